@@ -0,0 +1,288 @@
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use shinelink::bulk::{Input, Shift, bulk_process};
+use shinelink::decode::{self, decode_with};
+use shinelink::frame::Frame;
+use shinelink::squelch::{ChannelFilter, Config, EnergySquelch, Signal};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+
+#[derive(facet::Facet)]
+struct Args {
+    #[facet(positional)]
+    input_dir: PathBuf,
+
+    /// try every key offset if the aligned decrypt fails
+    #[facet(named, short = 'b')]
+    brute_force: bool,
+
+    /// how to find the bits: sweep (default), gardner or correlate
+    #[facet(named)]
+    clock: Option<String>,
+
+    /// if nothing matches its checksum, change up to this many doubtful bits
+    #[facet(named)]
+    repair: Option<usize>,
+
+    /// if nothing matches its checksum, splice out up to this many places where the bits slipped
+    #[facet(named)]
+    slips: Option<usize>,
+
+    /// serials we expect, e.g. KWK1CGQ11A/HZL0CGQ11A, comma separated, to find damaged frames
+    #[facet(named)]
+    known: Option<String>,
+
+    /// lowpass the IQ before demodulating, instead of averaging afterwards
+    #[facet(named)]
+    filter: bool,
+
+    /// squelch on power, opening and closing this far over the noise floor (dB), e.g. 10,6
+    #[facet(named)]
+    energy: Option<String>,
+
+    /// find the signal in captures without sigmf metadata, rather than assuming 476kHz
+    #[facet(named)]
+    auto_shift: bool,
+
+    /// a previous `--save`, to compare against
+    #[facet(named)]
+    baseline: Option<PathBuf>,
+
+    /// write this run's results, as json
+    #[facet(named)]
+    save: Option<PathBuf>,
+}
+
+/// bursts, by what they decoded to
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+struct Counts {
+    /// at least one frame with a valid checksum
+    perfect: usize,
+    /// no valid checksum, but something that looks like a frame
+    plausible: usize,
+    failed: usize,
+}
+
+impl Counts {
+    fn add(&mut self, other: Counts) {
+        self.perfect += other.perfect;
+        self.plausible += other.plausible;
+        self.failed += other.failed;
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+struct Report {
+    files: usize,
+    bursts: usize,
+    /// by packet type, or "unknown" if we couldn't even read the header
+    by_type: BTreeMap<String, Counts>,
+    /// by the burst's snr, rounded down to `SNR_BUCKET` dB
+    #[serde(default)]
+    by_snr: BTreeMap<i32, Counts>,
+    /// summed over all the threads, so roughly cpu time
+    decode_seconds: f64,
+}
+
+const SNR_BUCKET: f32 = 5.;
+
+fn main() -> Result<()> {
+    let usage = "usage: bench-decode [--brute-force] [--clock sweep|gardner|correlate] [--repair bits] [--slips n] [--known lanbox/link,..] [--filter] [--energy open,close] [--auto-shift] [--baseline old.json] [--save new.json] input_dir";
+    let args: Args = facet_args::from_std_args().context(usage)?;
+    // for `bulk_process`'s reports of where it found the signal
+    stderrlog::new().verbosity(2).init()?;
+
+    let baseline = args
+        .baseline
+        .as_ref()
+        .map(|path| -> Result<Report> {
+            let json = fs::read_to_string(path)
+                .with_context(|| format!("reading baseline {}", path.display()))?;
+            Ok(serde_json::from_str(&json)?)
+        })
+        .transpose()?;
+
+    // for captures without sigmf metadata
+    let config = Config {
+        decimation: 16,
+        sample_rate: 2_880_000,
+        deviation: 60_000,
+        shift: 476_000.,
+        filter: args.filter.then(ChannelFilter::default),
+        energy: args
+            .energy
+            .as_deref()
+            .map(str::parse::<EnergySquelch>)
+            .transpose()?,
+    };
+    let decode_config = decode::Config {
+        brute_force: args.brute_force,
+        clock: args.clock.as_deref().unwrap_or("sweep").parse()?,
+        repair_budget: args.repair.unwrap_or(0),
+        max_slips: args.slips.unwrap_or(0),
+        known: args
+            .known
+            .iter()
+            .flat_map(|v| v.split(','))
+            .map(str::parse)
+            .collect::<Result<_>>()?,
+        diagnostics: false,
+    };
+
+    let start = Instant::now();
+    let per_file = bulk_process(
+        |input, fms| Ok(bench(input, fms, &decode_config)),
+        &args.input_dir,
+        &config,
+        if args.auto_shift {
+            Shift::Found
+        } else {
+            Shift::Recorded
+        },
+    )?;
+    let wall = start.elapsed();
+
+    let mut report = Report::default();
+    for file in per_file {
+        report.files += file.files;
+        report.bursts += file.bursts;
+        report.decode_seconds += file.decode_seconds;
+        for (ty, counts) in file.by_type {
+            report.by_type.entry(ty).or_default().add(counts);
+        }
+        for (snr, counts) in file.by_snr {
+            report.by_snr.entry(snr).or_default().add(counts);
+        }
+    }
+
+    print(&report, baseline.as_ref());
+    println!(
+        "{} files, {} bursts, {:.2}s decoding ({:.2}s wall, including squelch)",
+        report.files,
+        report.bursts,
+        report.decode_seconds,
+        wall.as_secs_f64()
+    );
+
+    if let Some(path) = args.save {
+        fs::write(&path, serde_json::to_string_pretty(&report)? + "\n")
+            .with_context(|| format!("saving {}", path.display()))?;
+    }
+
+    Ok(())
+}
+
+fn bench(input: &Input, signals: &[Signal], config: &decode::Config) -> Report {
+    // 100us transitions
+    let edge_length = input.config.sample_rate as f32 / input.config.decimation as f32 / 10_000.;
+
+    let mut report = Report {
+        files: 1,
+        ..Report::default()
+    };
+    let mut spent = Duration::ZERO;
+    for signal in signals {
+        let start = Instant::now();
+        let decoded = decode_with(&signal.fm, edge_length, config);
+        spent += start.elapsed();
+        report.bursts += 1;
+
+        let perfect = decoded
+            .matches_crc
+            .keys()
+            .filter_map(|v| Frame::parse(v).ok())
+            .map(|frame| frame.packet_type)
+            .min();
+        let plausible = decoded
+            .looks_plausible
+            .iter()
+            .filter_map(|v| Frame::parse(v).ok())
+            .map(|frame| frame.packet_type)
+            .min();
+
+        let ty = perfect.or(plausible);
+        let key = ty.map_or_else(|| "unknown".to_string(), |ty| ty.to_string());
+        let counts = if perfect.is_some() {
+            Counts {
+                perfect: 1,
+                ..Counts::default()
+            }
+        } else if !decoded.looks_plausible.is_empty() {
+            Counts {
+                plausible: 1,
+                ..Counts::default()
+            }
+        } else {
+            Counts {
+                failed: 1,
+                ..Counts::default()
+            }
+        };
+        report.by_type.entry(key).or_default().add(counts);
+        let snr = (signal.snr / SNR_BUCKET).floor() as i32 * SNR_BUCKET as i32;
+        report.by_snr.entry(snr).or_default().add(counts);
+    }
+    report.decode_seconds = spent.as_secs_f64();
+    report
+}
+
+fn row(label: &str, now: Option<&Counts>, then: Option<&Counts>) {
+    let now = now.copied().unwrap_or_default();
+    let cell = |now: usize, then: Option<usize>| match then {
+        Some(then) if then != now => format!("{now} ({:+})", now as i64 - then as i64),
+        _ => now.to_string(),
+    };
+    println!(
+        "{label:>8} {:>14} {:>14} {:>14}",
+        cell(now.perfect, then.map(|c| c.perfect)),
+        cell(now.plausible, then.map(|c| c.plausible)),
+        cell(now.failed, then.map(|c| c.failed)),
+    );
+}
+
+fn print(report: &Report, baseline: Option<&Report>) {
+    let empty = BTreeMap::new();
+    let old = baseline.map_or(&empty, |b| &b.by_type);
+
+    let mut types = report.by_type.keys().chain(old.keys()).collect::<Vec<_>>();
+    // numerically, with "unknown" last
+    types.sort_by_key(|ty| (ty.parse::<u16>().unwrap_or(u16::MAX), ty.to_string()));
+    types.dedup();
+
+    println!(
+        "{:>8} {:>14} {:>14} {:>14}",
+        "type", "perfect", "plausible", "failed"
+    );
+    for ty in types {
+        row(ty, report.by_type.get(ty), old.get(ty));
+    }
+
+    // a decode failure at a high snr is the decoder's fault, rather than the signal's
+    let empty = BTreeMap::new();
+    let old = baseline.map_or(&empty, |b| &b.by_snr);
+    let mut snrs = report.by_snr.keys().chain(old.keys()).collect::<Vec<_>>();
+    snrs.sort();
+    snrs.dedup();
+    println!();
+    println!(
+        "{:>8} {:>14} {:>14} {:>14}",
+        "snr", "perfect", "plausible", "failed"
+    );
+    for snr in snrs {
+        row(&format!("{snr}dB"), report.by_snr.get(snr), old.get(snr));
+    }
+
+    if let Some(baseline) = baseline {
+        let perfect = |r: &Report| r.by_type.values().map(|c| c.perfect).sum::<usize>();
+        println!(
+            "perfect: {} -> {} ({:+}), decoding: {:.2}s -> {:.2}s",
+            perfect(baseline),
+            perfect(report),
+            perfect(report) as i64 - perfect(baseline) as i64,
+            baseline.decode_seconds,
+            report.decode_seconds
+        );
+    }
+}
@@ -0,0 +1,50 @@
+use anyhow::{Context, Result};
+use num_complex::Complex32;
+use shinelink::dsp::Nco;
+use std::hint::black_box;
+use std::time::Instant;
+
+#[derive(facet::Facet)]
+struct Args {
+    /// how many samples to mix, default 100 million (about 35s at 2.88Msps)
+    #[facet(named)]
+    samples: Option<usize>,
+}
+
+fn main() -> Result<()> {
+    let args: Args = facet_args::from_std_args().context("usage: bench-mix [--samples n]")?;
+    let samples = args.samples.unwrap_or(100_000_000);
+    let sample_rate = 2_880_000;
+    let shift = 476_000.;
+    let input = Complex32::new(0.5, -0.25);
+
+    // what the front ends used to do
+    let start = Instant::now();
+    let rate = std::f64::consts::TAU * shift / f64::from(sample_rate);
+    let mut total = Complex32::new(0., 0.);
+    for i in 0..samples {
+        let phase = rate * (i + 1) as f64;
+        total += black_box(input) * Complex32::new(phase.cos() as f32, phase.sin() as f32);
+    }
+    black_box(total);
+    report("cos/sin", samples, start);
+
+    let start = Instant::now();
+    let mut nco = Nco::new(shift, sample_rate);
+    let mut total = Complex32::new(0., 0.);
+    for _ in 0..samples {
+        total += nco.mix(black_box(input));
+    }
+    black_box(total);
+    report("nco", samples, start);
+
+    Ok(())
+}
+
+fn report(name: &str, samples: usize, start: Instant) {
+    let seconds = start.elapsed().as_secs_f64();
+    println!(
+        "{name:>8}: {:7.1} Msps ({seconds:.2}s)",
+        samples as f64 / seconds / 1e6
+    );
+}
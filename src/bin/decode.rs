@@ -1,5 +1,5 @@
 use anyhow::{Context, Result};
-use shinelink::decode::decode;
+use shinelink::decode::{self, decode_with};
 use shinelink::{read_to_end_f32, unambiguous};
 use std::path::PathBuf;
 
@@ -10,10 +10,30 @@ struct Args {
 
     #[facet(positional)]
     sample_rate: u32,
+
+    /// try every key offset if the aligned decrypt fails
+    #[facet(named, short = 'b')]
+    brute_force: bool,
+
+    /// how to find the bits: sweep (default), gardner or correlate
+    #[facet(named)]
+    clock: Option<String>,
+
+    /// if nothing matches its checksum, change up to this many doubtful bits
+    #[facet(named)]
+    repair: Option<usize>,
+
+    /// if nothing matches its checksum, splice out up to this many places where the bits slipped
+    #[facet(named)]
+    slips: Option<usize>,
+
+    /// serials we expect, e.g. KWK1CGQ11A/HZL0CGQ11A, comma separated, to find damaged frames
+    #[facet(named)]
+    known: Option<String>,
 }
 
 fn main() -> Result<()> {
-    let usage = "usage: decode file samplerate";
+    let usage = "usage: decode [--brute-force] [--clock sweep|gardner|correlate] [--repair bits] [--slips n] [--known lanbox/link,..] file samplerate";
 
     let args: Args = facet_args::from_std_args().context(usage)?;
 
@@ -22,14 +42,38 @@ fn main() -> Result<()> {
     // 100us transitions
     let edge_length = args.sample_rate as f32 / (1_000_000. / 100.);
 
-    let (matches_crc, looks_plausible) = decode(&input, edge_length);
+    let config = decode::Config {
+        brute_force: args.brute_force,
+        clock: args.clock.as_deref().unwrap_or("sweep").parse()?,
+        repair_budget: args.repair.unwrap_or(0),
+        max_slips: args.slips.unwrap_or(0),
+        known: args
+            .known
+            .iter()
+            .flat_map(|v| v.split(','))
+            .map(str::parse)
+            .collect::<Result<_>>()?,
+        diagnostics: false,
+    };
+    let decoded = decode_with(&input, edge_length, &config);
 
-    if !matches_crc.is_empty() {
-        for cand in &matches_crc {
-            println!("match: {} // {}", unambiguous(cand), hex::encode(cand));
+    if !decoded.matches_crc.is_empty() {
+        for (cand, phase) in &decoded.matches_crc {
+            let mut how = format!("{phase:?}");
+            if let Some(bits) = decoded.repaired.get(cand) {
+                how += &format!(", {bits} bits repaired");
+            }
+            if let Some(slips) = decoded.slips.get(cand) {
+                how += &format!(", {slips} slips corrected");
+            }
+            println!(
+                "match ({how}): {} // {}",
+                unambiguous(cand),
+                hex::encode(cand)
+            );
         }
     } else {
-        for decrypted in &looks_plausible {
+        for decrypted in &decoded.looks_plausible {
             println!(
                 "no match: {} // {}",
                 unambiguous(decrypted),
@@ -1,7 +1,8 @@
 use anyhow::{Context, Result};
-use shinelink::bulk::bulk_process;
-use shinelink::decode::decode;
-use shinelink::squelch::Config;
+use shinelink::bulk::{Input, Shift, bulk_process};
+use shinelink::decode::{self, decode_with};
+use shinelink::frame::Frame;
+use shinelink::squelch::{Config, EnergySquelch, Signal};
 use std::collections::HashSet;
 use std::path::PathBuf;
 
@@ -9,34 +10,87 @@ use std::path::PathBuf;
 struct Args {
     #[facet(positional)]
     input_dir: PathBuf,
+
+    /// where the signal is in every capture, whatever its sigmf metadata says; otherwise, from
+    /// the metadata, or found in the capture
+    #[facet(named)]
+    shift: Option<f64>,
+
+    /// squelch on power, opening and closing this far over the noise floor (dB), e.g. 10,6
+    #[facet(named)]
+    energy: Option<String>,
+
+    /// print a json object per burst, with how it looked to the decoder
+    #[facet(named)]
+    json: bool,
 }
 
 fn main() -> Result<()> {
-    let args: Args = facet_args::from_std_args().context("usage: find-172 input_dir")?;
+    let args: Args = facet_args::from_std_args()
+        .context("usage: find-172 [--shift 476000] [--energy open,close] [--json] input_dir")?;
+    // for `bulk_process`'s reports of where it found the signal
+    stderrlog::new().verbosity(2).init()?;
 
     let config = Config {
         decimation: 16,
         sample_rate: 2_880_000,
         deviation: 60_000,
-        shift: 476_000.,
+        shift: args.shift.unwrap_or(476_000.),
+        filter: None,
+        energy: args
+            .energy
+            .as_deref()
+            .map(str::parse::<EnergySquelch>)
+            .transpose()?,
     };
 
-    bulk_process(find_172, &args.input_dir, &config)?;
+    bulk_process(
+        |input, signals| find_172(input, signals, args.json),
+        &args.input_dir,
+        &config,
+        match args.shift {
+            Some(_) => Shift::Given,
+            None => Shift::Found,
+        },
+    )?;
 
     Ok(())
 }
 
-fn find_172(file_name: &str, fms: &[(usize, Vec<f32>)]) -> Result<()> {
-    for (offset, fm) in fms {
+fn find_172(input: &Input, signals: &[Signal], json: bool) -> Result<()> {
+    let file_name = &input.file_name;
+    for signal in signals {
+        let (offset, fm) = (signal.end, &signal.fm);
         // all perfect examples we've seen are between 32448 and 32512, so this is quite a wide window
         if fm.len() < 30_000 || fm.len() > 34_000 {
             continue;
         }
-        let (crc, rest) = decode(&fm, 18.);
+        let config = decode::Config {
+            diagnostics: json,
+            ..decode::Config::default()
+        };
+        let decoded = decode_with(fm, 18., &config);
+        let diagnostics = decoded.diagnostics.map(|d| d.with_signal(signal));
+        let crc = decoded.matches_crc.into_keys().collect();
+        let outcome = classify(crc, decoded.looks_plausible)?;
+        if json {
+            println!(
+                "{}",
+                serde_json::json!({
+                    "file": file_name,
+                    "end": offset,
+                    "len": fm.len(),
+                    "outcome": format!("{outcome:?}"),
+                    "diagnostics": diagnostics,
+                })
+            );
+            continue;
+        }
         println!(
-            "{file_name:65} {offset:6} {:6} {:?}",
+            "{file_name:65} {offset:6} {:6} {:5.1}dB {:?}",
             fm.len(),
-            classify(crc, rest)?
+            signal.snr,
+            outcome
         );
     }
     Ok(())
@@ -44,7 +98,7 @@ fn find_172(file_name: &str, fms: &[(usize, Vec<f32>)]) -> Result<()> {
 
 #[derive(Debug)]
 enum Outcome {
-    Perfect(u8),
+    Perfect(u16),
     Plausible(u8),
     Bad(u8),
     None,
@@ -53,29 +107,17 @@ enum Outcome {
 fn classify(crc: HashSet<Vec<u8>>, rest: HashSet<Vec<u8>>) -> Result<Option<Outcome>> {
     let mut crc_matches = HashSet::new();
     for sample in &crc {
-        if !sn_packet(&sample) {
+        let Ok(frame) = Frame::parse(sample) else {
+            continue;
+        };
+        if frame.payload.is_empty() {
             continue;
         }
-        crc_matches.insert(sample[28]);
+        crc_matches.insert(frame.packet_type);
     }
     if crc_matches.len() == 1 {
-        let seq = crc_matches.iter().next().unwrap();
-        return Ok(Some(Outcome::Perfect(*seq)));
+        let ty = crc_matches.iter().next().unwrap();
+        return Ok(Some(Outcome::Perfect(*ty)));
     }
     Ok(None)
 }
-
-fn sn_packet(sample: &[u8]) -> bool {
-    if !sample.starts_with(b"RF") || sample.len() < 30 {
-        return false;
-    }
-    let sn = &sample[6..26];
-    if !sn
-        .iter()
-        .all(|v| v.is_ascii_uppercase() || v.is_ascii_digit())
-    {
-        return false;
-    }
-
-    true
-}
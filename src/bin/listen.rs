@@ -0,0 +1,163 @@
+use anyhow::{Context, Result, bail};
+use shinelink::frame::Frame;
+use shinelink::iq::{self, Format};
+use shinelink::listen::listen;
+use shinelink::session::{Event, Tracker};
+use shinelink::source::{RtlSdr, RtlTcp};
+use shinelink::squelch::Config;
+use shinelink::unambiguous;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+#[derive(facet::Facet)]
+struct Args {
+    /// read a capture instead of a dongle
+    #[facet(named, short = 'f')]
+    file: Option<PathBuf>,
+
+    /// the capture's format: cu8, cs8, cs16, cf32 or wav; from its extension by default
+    #[facet(named)]
+    format: Option<String>,
+
+    /// use a dongle on another machine, via rtl_tcp, e.g. `192.168.1.2:1234`
+    #[facet(named, short = 't')]
+    rtl_tcp: Option<String>,
+
+    /// which dongle, default 0
+    #[facet(named, short = 'd')]
+    device: Option<usize>,
+
+    /// where to tune the dongle (Hz), default 434.2MHz
+    #[facet(named)]
+    frequency: Option<u32>,
+
+    /// default 2.88Msps, or what a wav capture's header says
+    #[facet(named)]
+    sample_rate: Option<u32>,
+
+    /// tenths of a dB, default automatic
+    #[facet(named, short = 'g')]
+    gain: Option<i32>,
+
+    /// where the signal is, relative to the tuned frequency (Hz), default 476kHz below
+    #[facet(named)]
+    shift: Option<f64>,
+}
+
+fn main() -> Result<()> {
+    let args: Args = facet_args::from_std_args().context(
+        "usage: listen [--file capture.cu8 | --rtl-tcp host:port | --device 0] [--frequency 434200000] [--sample-rate 2880000] [--gain 400] [--shift 476000]",
+    )?;
+
+    let mut file = args
+        .file
+        .map(|path| -> Result<_> {
+            let format = args.format.map(|f| f.parse::<Format>()).transpose()?;
+            iq::open(path, format)
+        })
+        .transpose()?;
+
+    // a wav says what its sample rate is
+    let sample_rate = match (
+        args.sample_rate,
+        file.as_ref().and_then(|f| f.sample_rate()),
+    ) {
+        (Some(arg), Some(header)) if arg != header => {
+            bail!("--sample-rate {arg} disagrees with the wav header's {header}")
+        }
+        (arg, header) => arg.or(header),
+    };
+
+    let config = Config {
+        decimation: 16,
+        sample_rate: sample_rate.unwrap_or(2_880_000),
+        deviation: 60_000,
+        shift: args.shift.unwrap_or(476_000.),
+        filter: None,
+        energy: None,
+    };
+
+    let stop = Arc::new(AtomicBool::new(false));
+    {
+        let stop = Arc::clone(&stop);
+        ctrlc::set_handler(move || stop.store(true, Ordering::Relaxed))?;
+    }
+
+    let chunk_seconds =
+        (config.decimation * shinelink::squelch::CHUNK_BY) as f64 / config.sample_rate as f64;
+    let mut tracker = Tracker::new((TRANSACTION_TIMEOUT / chunk_seconds) as usize);
+    let mut print = |offset: usize, frame: Frame| {
+        println!(
+            "{:10.3}s {} {}{} {:3} {}",
+            offset as f64 * chunk_seconds,
+            frame.seq,
+            frame.lanbox_serial,
+            frame.link_serial,
+            frame.packet_type,
+            unambiguous(&frame.payload)
+        );
+        for event in tracker.push(offset, frame) {
+            print_event(&event, chunk_seconds);
+        }
+    };
+
+    let frequency = args.frequency.unwrap_or(434_200_000);
+
+    match (&mut file, args.rtl_tcp) {
+        (Some(source), _) => {
+            listen(source, &config, &stop, &mut print)?;
+        }
+        (None, Some(addr)) => {
+            let mut source =
+                RtlTcp::connect(&addr).with_context(|| format!("connecting to {addr}"))?;
+            source.set_sample_rate(config.sample_rate)?;
+            source.set_center_freq(frequency)?;
+            source.set_gain(args.gain)?;
+            eprintln!("listening to {addr} ({:?}), ^C to stop", source.info);
+            listen(&mut source, &config, &stop, &mut print)?;
+        }
+        (None, None) => {
+            let mut source = RtlSdr::open(
+                args.device.unwrap_or(0),
+                frequency,
+                config.sample_rate,
+                args.gain,
+            )?;
+            eprintln!("listening, ^C to stop");
+            listen(&mut source, &config, &stop, &mut print)?;
+        }
+    }
+
+    for event in tracker.finish() {
+        print_event(&event, chunk_seconds);
+    }
+
+    Ok(())
+}
+
+/// how long (s) after a request we wait for its responses
+const TRANSACTION_TIMEOUT: f64 = 2.;
+
+fn print_event(event: &Event, chunk_seconds: f64) {
+    let (t, outcome) = match event {
+        Event::Answered(t) => (
+            t,
+            format!(
+                "answered after {:.3}s, by {} distinct responses",
+                t.latency().unwrap_or(0) as f64 * chunk_seconds,
+                t.responses.len()
+            ),
+        ),
+        Event::Unanswered(t) => (t, "unanswered".to_string()),
+        // it's already been printed, and there's nothing to add
+        Event::Unsolicited(_) => return,
+    };
+    println!(
+        "{:10.3}s {} request for {}, asked {} times: {outcome}",
+        t.request.offset as f64 * chunk_seconds,
+        t.request.frame.seq,
+        t.request.frame.packet_type,
+        t.request_copies,
+    );
+}
@@ -1,10 +1,10 @@
 use anyhow::anyhow;
 use anyhow::{Result, bail, ensure};
 use itertools::Itertools;
-use num_complex::Complex32;
 use shinelink::demod_fm::FmDemod;
+use shinelink::dsp::Nco;
 use shinelink::{bits_to_byte, read_one_complex_f32};
-use std::f32::consts::{PI, TAU};
+use std::f32::consts::PI;
 use std::io::Write;
 use std::{env, fs, io};
 
@@ -28,8 +28,6 @@ fn main() -> Result<()> {
         "shift must be less than half the sample rate"
     );
 
-    let shift_rate = TAU * shift / sample_rate as f32;
-
     let mut out = io::BufWriter::new(fs::File::create(format!(
         "out.sr{}.f32",
         sample_rate as usize / decimation
@@ -40,12 +38,9 @@ fn main() -> Result<()> {
     let mut buf = Vec::with_capacity(64);
     let mut observations = Vec::new();
 
-    // there's probably a dsp way to do this
-    let mut i = 0f32;
-    while let Some(mut sample) = read_one_complex_f32(&mut inp)? {
-        i += 1.;
-        sample *= Complex32::new((shift_rate * i).cos(), (shift_rate * i).sin());
-        buf.push(demod.update(sample));
+    let mut nco = Nco::new(f64::from(shift), sample_rate);
+    while let Some(sample) = read_one_complex_f32(&mut inp)? {
+        buf.push(demod.update(nco.mix(sample)));
 
         if buf.len() >= decimation {
             let mean = buf.iter().sum::<f32>() / buf.len() as f32;
@@ -1,8 +1,11 @@
 use anyhow::{Context, Result};
 use itertools::Itertools;
-use shinelink::bulk::bulk_process;
-use shinelink::decode::decode;
-use shinelink::squelch::Config;
+use shinelink::bulk::{Input, Shift, bulk_process, save_annotations};
+use shinelink::combine::{Burst, combine};
+use shinelink::decode::{self, candidates, decode_candidates, diagnose};
+use shinelink::frame::Frame;
+use shinelink::sigmf::{Annotation, burst_annotation};
+use shinelink::squelch::{CHUNK_BY, Config, EnergySquelch, Signal};
 use shinelink::unambiguous;
 use std::fs;
 use std::io::Write;
@@ -12,64 +15,197 @@ use std::path::PathBuf;
 struct Args {
     #[facet(positional)]
     input_dir: PathBuf,
+
+    /// where the signal is in every capture, whatever its sigmf metadata says; otherwise, from
+    /// the metadata, or found in the capture
+    #[facet(named)]
+    shift: Option<f64>,
+
+    /// squelch on power, opening and closing this far over the noise floor (dB), e.g. 10,6;
+    /// rather than on how clean the demodulated signal looks
+    #[facet(named)]
+    energy: Option<String>,
+
+    /// print a json object per burst, with how it looked to the decoder, instead of the frames
+    #[facet(named)]
+    json: bool,
 }
 
 fn main() -> Result<()> {
-    let args: Args = facet_args::from_std_args().context("usage: perfect-packets input_dir")?;
+    let args: Args = facet_args::from_std_args().context(
+        "usage: perfect-packets [--shift 476000] [--energy open,close] [--json] input_dir",
+    )?;
+    // for `bulk_process`'s reports of where it found the signal
+    stderrlog::new().verbosity(2).init()?;
 
+    // for captures without sigmf metadata
     let config = Config {
         decimation: 16,
         sample_rate: 2_880_000,
         deviation: 60_000,
-        shift: 476_000.,
+        shift: args.shift.unwrap_or(476_000.),
+        filter: None,
+        energy: args
+            .energy
+            .as_deref()
+            .map(str::parse::<EnergySquelch>)
+            .transpose()?,
     };
 
-    bulk_process(capture_very_high_quality_packets, &args.input_dir, &config)?;
+    bulk_process(
+        |input, signals| capture_very_high_quality_packets(input, signals, args.json),
+        &args.input_dir,
+        &config,
+        match args.shift {
+            Some(_) => Shift::Given,
+            None => Shift::Found,
+        },
+    )?;
 
     Ok(())
 }
 
-fn capture_very_high_quality_packets(file_name: &str, fms: &[(usize, Vec<f32>)]) -> Result<()> {
-    for (n, fm) in fms {
-        let (crc, _rest) = decode(&fm, 18.);
-        let good = crc
-            .iter()
-            .filter(|v| v.starts_with(b"RF") && v.len() > 30)
+fn capture_very_high_quality_packets(input: &Input, signals: &[Signal], json: bool) -> Result<()> {
+    let file_name = &input.file_name;
+    // 100us transitions
+    let edge_length = input.config.sample_rate as f32 / input.config.decimation as f32 / 10_000.;
+    // repeats seem to come well within a second
+    let window = input.config.sample_rate as usize / (input.config.decimation * CHUNK_BY);
+    let mut annotations = Vec::with_capacity(signals.len());
+    let mut failed = Vec::new();
+
+    for signal in signals {
+        let (n, fm) = (signal.end, &signal.fm);
+        let config = decode::Config::default();
+        let cands = candidates(fm, edge_length, &config);
+        let decoded = decode_candidates(&cands, &config);
+        let good = decoded
+            .matches_crc
+            .keys()
+            .filter_map(|v| Frame::parse(v).ok())
             .collect_vec();
+        if json {
+            let diagnostics = diagnose(fm, edge_length, &decoded).with_signal(signal);
+            println!(
+                "{}",
+                serde_json::json!({
+                    "file": file_name,
+                    "end": n,
+                    "packet_types": good.iter().map(|frame| frame.packet_type).collect_vec(),
+                    "plausible": !decoded.looks_plausible.is_empty(),
+                    "diagnostics": diagnostics,
+                })
+            );
+        }
 
-        for good in good {
-            let sn = &good[6..26];
-            if !sn
-                .iter()
-                .all(|v| v.is_ascii_uppercase() || v.is_ascii_digit())
-            {
-                continue;
-            }
+        let burst = burst_annotation(&input.config, signal);
+        if good.is_empty() {
+            annotations.push(Annotation {
+                label: Some(
+                    if decoded.looks_plausible.is_empty() {
+                        "no sync"
+                    } else {
+                        "bad crc"
+                    }
+                    .to_string(),
+                ),
+                crc: Some(false),
+                ..burst.clone()
+            });
+            failed.push(Burst {
+                offset: n,
+                candidates: cands,
+            });
+        }
+
+        for frame in good {
+            annotations.push(Annotation {
+                label: Some(format!("type {}", frame.packet_type)),
+                comment: Some(format!(
+                    "seq {} {}{}",
+                    frame.seq, frame.lanbox_serial, frame.link_serial
+                )),
+                packet_type: Some(frame.packet_type),
+                crc: Some(true),
+                ..burst.clone()
+            });
 
-            let data = good[29..].to_vec();
-            if data.len() < 4 {
+            if frame.payload.len() < 4 {
                 continue;
             }
-            let pkt = Pkt {
-                seq: good[2],
-                prefix: good[3..6].try_into()?,
-                sn: String::from_utf8_lossy(&sn).to_string(),
-                req: u16::from_be_bytes(good[27..=28].try_into()?),
-            };
-            println!("{} {n:6} {:?} {}", file_name, pkt, unambiguous(&data),);
-            let mut file = fs::File::create(format!("{}.{n}.{}.pkt", file_name, pkt.req))?;
-            file.write_all(&data)?;
-            file.flush()?;
+            if !json {
+                println!(
+                    "{} {n:6} {} {:?} {}{} {} {}",
+                    file_name,
+                    frame.seq,
+                    frame.version,
+                    frame.lanbox_serial,
+                    frame.link_serial,
+                    frame.packet_type,
+                    unambiguous(&frame.payload),
+                );
+            }
+            write_pkt(file_name, n, &frame)?;
+        }
+    }
+
+    // bursts which only decode together, annotated at the first of them
+    for combined in combine(&failed, window) {
+        let Ok(frame) = Frame::parse(&combined.frame) else {
+            continue;
+        };
+        let n = combined.offset;
+        let signal = signals.iter().find(|s| s.end == n).expect("from signals");
+        annotations.push(Annotation {
+            label: Some(format!("type {}", frame.packet_type)),
+            comment: Some(format!(
+                "seq {} {}{}, combined from {} copies",
+                frame.seq, frame.lanbox_serial, frame.link_serial, combined.copies
+            )),
+            packet_type: Some(frame.packet_type),
+            crc: Some(true),
+            ..burst_annotation(&input.config, signal)
+        });
+        if frame.payload.len() >= 4 {
+            write_pkt(file_name, n, &frame)?;
         }
+        if json {
+            println!(
+                "{}",
+                serde_json::json!({
+                    "file": file_name,
+                    "end": n,
+                    "packet_types": [frame.packet_type],
+                    "combined_from": combined.copies,
+                })
+            );
+            continue;
+        }
+        println!(
+            "{} {n:6} {} {:?} {}{} {} {} (combined from {})",
+            file_name,
+            frame.seq,
+            frame.version,
+            frame.lanbox_serial,
+            frame.link_serial,
+            frame.packet_type,
+            unambiguous(&frame.payload),
+            combined.copies,
+        );
+    }
+
+    // where `bulk_process` will find them next time, or beside someone else's recording
+    if let Err(e) = save_annotations(input, annotations) {
+        eprintln!("not annotating {file_name}: {e}");
     }
 
     Ok(())
 }
 
-#[derive(Debug)]
-struct Pkt {
-    seq: u8,
-    prefix: [u8; 3],
-    sn: String,
-    req: u16,
+/// `{file_name}.{n}.{packet_type}.pkt`, the payload, for later tools
+fn write_pkt(file_name: &str, n: usize, frame: &Frame) -> Result<()> {
+    let mut file = fs::File::create(format!("{file_name}.{n}.{}.pkt", frame.packet_type))?;
+    file.write_all(&frame.payload)?;
+    file.flush()?;
+    Ok(())
 }
@@ -1,27 +1,48 @@
-use anyhow::{Context, Result, anyhow};
-use shinelink::squelch::{Config, squelch};
+use anyhow::{Context, Result, anyhow, bail};
+use shinelink::decode::decode;
+use shinelink::frame::Frame;
+use shinelink::iq::{self, Format};
+use shinelink::offset::{self, FREQUENCY, SHIFT, ppm};
+use shinelink::sigmf::{self, Annotation, DEMODULATED, Meta, burst_annotation, has_meta};
+use shinelink::squelch::{Config, EnergySquelch, Signal, squelch_source};
 use std::io::Write;
 use std::path::PathBuf;
 use std::{fs, io};
 
 #[derive(facet::Facet)]
 struct Args {
+    /// a capture, or either file of a sigmf recording; a capture's `{file}.sigmf-meta` is used
+    /// if it has one
     #[facet(positional)]
     path: PathBuf,
 
-    #[facet(positional)]
-    sample_rate: u32,
+    /// required, unless the recording's sigmf metadata, or the wav header, says
+    #[facet(named)]
+    sample_rate: Option<u32>,
 
-    #[facet(positional)]
-    deviation: u32,
+    /// default 60kHz
+    #[facet(named)]
+    deviation: Option<u32>,
 
-    #[facet(positional)]
-    shift: f64,
+    /// from the recording's sigmf metadata, or found in the capture, by default
+    #[facet(named)]
+    shift: Option<f64>,
+
+    /// squelch on power, opening and closing this far over the noise floor (dB), e.g. 10,6
+    #[facet(named)]
+    energy: Option<String>,
+
+    /// cu8, cs8, cs16, cf32 or wav; from the file's extension by default
+    #[facet(named)]
+    format: Option<String>,
 }
 
 fn main() -> Result<()> {
-    let args: Args =
-        facet_args::from_std_args().context("usage: file sample_rate deviation shift")?;
+    let args: Args = facet_args::from_std_args().context(
+        "usage: squelcher [--sample-rate 2880000] [--deviation 60000] [--shift 476000] [--energy open,close] [--format cu8] file\n\
+         (the sample rate, deviation and shift used to be positional; they are flags now)\n\
+         writes each burst to {file}.{chunk}.squelch.sigmf-data, with a .sigmf-meta",
+    )?;
 
     let original_file_name = args
         .path
@@ -29,34 +50,134 @@ fn main() -> Result<()> {
         .ok_or(anyhow!("input file must have a name"))?
         .to_string_lossy()
         .to_string();
-    let mut inp = io::BufReader::new(fs::File::open(args.path)?);
 
-    let config = Config {
+    let format = args.format.map(|f| f.parse::<Format>()).transpose()?;
+    let open = || -> Result<_> {
+        Ok(if has_meta(&args.path) {
+            let (meta, inp) = sigmf::open(&args.path)?;
+            (Some(meta), inp)
+        } else {
+            (None, iq::open(&args.path, format)?)
+        })
+    };
+    let (meta, mut inp) = open()?;
+
+    let sample_rate = match (args.sample_rate, inp.sample_rate()) {
+        (Some(arg), Some(header)) if arg != header => {
+            bail!("--sample-rate {arg} disagrees with the wav header's {header}")
+        }
+        (arg, header) => arg.or(header),
+    };
+    let mut config = Config {
         decimation: 16,
-        sample_rate: args.sample_rate,
-        deviation: args.deviation,
-        shift: args.shift,
+        sample_rate: sample_rate
+            .or(meta
+                .as_ref()
+                .and_then(|m| m.global.sample_rate)
+                .map(|v| v.round() as u32))
+            .context("--sample-rate is required")?,
+        deviation: args
+            .deviation
+            .or(meta.as_ref().and_then(|m| m.global.deviation))
+            .unwrap_or(60_000),
+        shift: 0.,
+        filter: None,
+        energy: args
+            .energy
+            .as_deref()
+            .map(str::parse::<EnergySquelch>)
+            .transpose()?,
+    };
+    config.shift = match args.shift.or(meta.as_ref().and_then(|m| m.global.shift)) {
+        Some(shift) => shift,
+        None => {
+            let track = offset::track(&mut open()?.1, &config, 60.)?;
+            let shift = track
+                .shift()
+                .context("couldn't find the signal, so --shift is required")?;
+            let frequency = meta
+                .as_ref()
+                .and_then(|m| m.captures.first())
+                .and_then(|c| c.frequency)
+                .unwrap_or(FREQUENCY);
+            println!(
+                "found the signal at {:.0}Hz, {:+.1}ppm from the usual {:.0}Hz",
+                -shift,
+                ppm(shift, SHIFT, frequency),
+                -SHIFT,
+            );
+            shift
+        }
     };
 
-    let merged = squelch(&mut inp, &config)?;
+    let merged = squelch_source(&mut inp, &config)?;
 
     let decimated_sample_rate = config.sample_rate as usize / config.decimation;
+    // 100us transitions
+    let edge_length = decimated_sample_rate as f32 / 10_000.;
 
-    for (nth, flattened) in merged.into_iter() {
-        let name = format!("{original_file_name}.{nth}.squelch.sr{decimated_sample_rate}.f32");
-        let mut file = io::BufWriter::new(fs::File::create(&name)?);
+    for signal in merged {
+        let name = format!("{original_file_name}.{}.squelch", signal.end);
+        let data = format!("{name}.sigmf-data");
+        let mut file = io::BufWriter::new(fs::File::create(&data)?);
 
-        for obs in &flattened {
+        for obs in &signal.fm {
             file.write_all(&obs.to_le_bytes())?;
         }
         file.flush()?;
 
+        let mut meta = Meta::new(DEMODULATED, decimated_sample_rate as u32);
+        meta.global.description = Some(format!("demodulated from {original_file_name}"));
+        meta.annotations = annotate(&config, edge_length, &signal);
+        meta.write(format!("{name}.sigmf-meta"))?;
+
         println!(
-            "wrote {} samples to {}",
-            flattened.len(),
-            fs::canonicalize(name)?.display()
+            "wrote {} samples ({:.1}dB snr) to {}",
+            signal.fm.len(),
+            signal.snr,
+            fs::canonicalize(data)?.display()
         );
     }
 
     Ok(())
 }
+
+/// the whole snippet, marked with what it decoded to, and where it came from
+fn annotate(config: &Config, edge_length: f32, signal: &Signal) -> Vec<Annotation> {
+    let fm = &signal.fm;
+    let burst = burst_annotation(config, signal);
+    let whole = Annotation {
+        sample_start: 0,
+        sample_count: Some(fm.len() as u64),
+        comment: Some(format!("from sample {} of the capture", burst.sample_start)),
+        ..burst
+    };
+
+    let (crc, rest) = decode(fm, edge_length);
+    let frames = crc
+        .iter()
+        .filter_map(|v| Frame::parse(v).ok())
+        .collect::<Vec<_>>();
+    if frames.is_empty() {
+        let label = if rest.is_empty() {
+            "no sync"
+        } else {
+            "bad crc"
+        };
+        return vec![Annotation {
+            label: Some(label.to_string()),
+            crc: Some(false),
+            ..whole
+        }];
+    }
+
+    frames
+        .into_iter()
+        .map(|frame| Annotation {
+            label: Some(format!("type {}", frame.packet_type)),
+            packet_type: Some(frame.packet_type),
+            crc: Some(true),
+            ..whole.clone()
+        })
+        .collect()
+}
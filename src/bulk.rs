@@ -1,14 +1,53 @@
-use crate::squelch::{Config, squelch};
+use crate::iq::{self, Format};
+use crate::offset::{self, FREQUENCY, Track, ppm};
+use crate::sigmf::{self, Annotation, Meta, has_meta};
+use crate::squelch::{Config, Signal, squelch_source};
 use anyhow::Result;
 use rayon::prelude::*;
-use std::path::Path;
-use std::{fs, io};
+use std::fs;
+use std::path::{Path, PathBuf};
 
+/// a capture being processed
+pub struct Input {
+    pub path: PathBuf,
+    pub file_name: String,
+    /// as found on disk, e.g. `Wav`
+    pub format: Format,
+    /// from the capture's sigmf metadata (or wav header), if it has any, otherwise the defaults,
+    /// with the shift found in the capture, if `auto_shift`
+    pub config: Config,
+    /// the capture's, if it's a sigmf recording or has been described in place; see
+    /// `sigmf::meta_path`
+    pub meta: Option<Meta>,
+    /// where the signal was found, if we looked
+    pub track: Option<Track>,
+}
+
+/// how long each estimate of the shift covers (s)
+const TRACK_WINDOW: f64 = 60.;
+
+/// where the signal is in each capture
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Shift {
+    /// `config.shift`, whatever the capture's metadata says, e.g. from `--shift`
+    Given,
+    /// from the capture's metadata, or `config.shift`
+    Recorded,
+    /// from the capture's metadata, or found in the capture; `config.shift` is only what we
+    /// expect, for reporting how far off the dongle is
+    Found,
+}
+
+/// Squelch every capture in `input_dir`, and pass the bursts to `func`. A capture's sigmf
+/// metadata is used if it's a recording, or if there's a `{file_name}.sigmf-meta` next to it;
+/// a wav's sample rate comes from its header.
 pub fn bulk_process<T: Send>(
-    func: impl Sync + Send + Fn(&str, &[(usize, Vec<f32>)]) -> Result<T>,
+    func: impl Sync + Send + Fn(&Input, &[Signal]) -> Result<T>,
     input_dir: impl AsRef<Path>,
     config: &Config,
+    shift: Shift,
 ) -> Result<Vec<T>> {
+    let given = config.shift;
     let mut files = Vec::new();
     for f in fs::read_dir(input_dir)? {
         let f = f?;
@@ -16,7 +55,8 @@ pub fn bulk_process<T: Send>(
             continue;
         }
         let path = f.path();
-        if path.extension() != Some("cu8".as_ref()) {
+        let is_data = path.extension() == Some("sigmf-data".as_ref());
+        if Format::from_path(&path).is_none() && !is_data {
             continue;
         }
         files.push(f.path());
@@ -31,8 +71,225 @@ pub fn bulk_process<T: Send>(
                 .display()
                 .to_string();
 
-            let fms = squelch(&mut io::BufReader::new(fs::File::open(&f)?), &config)?;
-            func(&file_name, &fms)
+            let open = || -> Result<_> {
+                Ok(if has_meta(&f) {
+                    let (meta, inp) = sigmf::open(&f)?;
+                    (Some(meta), inp)
+                } else {
+                    (None, iq::open(&f, Format::from_path(&f))?)
+                })
+            };
+            let (meta, mut inp) = open()?;
+            let (format, mut config) = match &meta {
+                Some(meta) => (meta.format()?, meta.config(config)),
+                None => (
+                    Format::from_path(&f).expect("filtered above"),
+                    Config {
+                        // a wav's header says
+                        sample_rate: inp.sample_rate().unwrap_or(config.sample_rate),
+                        ..config.clone()
+                    },
+                ),
+            };
+            if shift == Shift::Given {
+                config.shift = given;
+            }
+
+            let mut track = None;
+            if shift == Shift::Found && meta.as_ref().is_none_or(|m| m.global.shift.is_none()) {
+                let found = offset::track(&mut open()?.1, &config, TRACK_WINDOW)?;
+                let frequency = meta
+                    .as_ref()
+                    .and_then(|m| m.captures.first())
+                    .and_then(|c| c.frequency)
+                    .unwrap_or(FREQUENCY);
+                match found.shift() {
+                    Some(shift) => {
+                        log::info!(
+                            "{file_name}: shift {shift:.0}Hz, {:+.1}ppm from {:.0}Hz{}",
+                            ppm(shift, config.shift, frequency),
+                            config.shift,
+                            found.drift().map_or(String::new(), |drift| format!(
+                                ", drifting {:+.1}Hz/min",
+                                drift * 60.
+                            )),
+                        );
+                        config.shift = shift;
+                    }
+                    None => log::warn!(
+                        "{file_name}: no signal found, assuming a shift of {:.0}Hz",
+                        config.shift
+                    ),
+                }
+                track = Some(found);
+            }
+
+            let signals = squelch_source(&mut inp, &config)?;
+            let input = Input {
+                path: f,
+                file_name,
+                format,
+                config,
+                meta,
+                track,
+            };
+            func(&input, &signals)
         })
         .collect()
 }
+
+/// Record `annotations` for `input`, where `bulk_process` will find them: in place for a
+/// plain capture, or a recording we made, with any shift found in it, so the next run needn't
+/// look for it. Anyone else's recording is left alone, and described in its
+/// `sigmf::sidecar_path` instead, without the shift. Returns where they went.
+pub fn save_annotations(input: &Input, annotations: Vec<Annotation>) -> Result<PathBuf> {
+    let found = input.track.as_ref().and_then(Track::shift);
+    let (mut meta, path) = match &input.meta {
+        Some(theirs) if !theirs.is_ours() => {
+            let mut meta = theirs.clone();
+            // the sidecar isn't named after the data
+            meta.global
+                .dataset
+                .get_or_insert_with(|| input.file_name.clone());
+            (meta, sigmf::sidecar_path(&input.path))
+        }
+        Some(ours) => {
+            let mut meta = ours.clone();
+            if let Some(found) = found {
+                meta.global.shift = Some(found);
+            }
+            (meta, sigmf::meta_path(&input.path))
+        }
+        None => {
+            let mut meta =
+                Meta::for_capture(input.format, &input.config, Some(input.file_name.clone()))?;
+            // a shift we were told, or assumed, might not be right next time
+            meta.global.shift = found;
+            (meta, sigmf::meta_path(&input.path))
+        }
+    };
+    meta.annotate(annotations);
+    meta.write(&path)?;
+    Ok(path)
+}
+
+/// a capture of one frame, at `offset` (Hz), as `cf32`
+#[cfg(test)]
+fn write_capture(path: &Path, offset: f64) {
+    use crate::decode::synthesise;
+    use crate::encode::encode;
+    use crate::frame::Frame;
+    use crate::modulate;
+
+    let frame = Frame::parse(b"RF9\x01\x00\x10KWK1CGQ11AHZL0CGQ11A\x03\x00\x04\x00\x01\x02\x03")
+        .expect("valid frame");
+    let capture = synthesise(
+        &encode(&frame),
+        &modulate::Config {
+            offset,
+            bt: Some(0.5),
+            ..modulate::Config::default()
+        },
+    );
+    let bytes = capture
+        .iter()
+        .flat_map(|v| [v.re.to_le_bytes(), v.im.to_le_bytes()])
+        .flatten()
+        .collect::<Vec<u8>>();
+    fs::write(path, bytes).expect("temp file");
+}
+
+#[test]
+fn test_meta_round_trip() {
+    let dir = std::env::temp_dir().join(format!("shinelink-bulk-{}", std::process::id()));
+    fs::create_dir_all(&dir).expect("temp dir");
+    write_capture(&dir.join("capture.cf32"), -300_000.);
+
+    let config = Config {
+        decimation: 16,
+        sample_rate: 2_880_000,
+        deviation: 60_000,
+        shift: 476_000.,
+        filter: None,
+        energy: None,
+    };
+    // as perfect-packets records what it found
+    let record = |input: &Input, signals: &[Signal]| -> Result<_> {
+        save_annotations(input, Vec::new())?;
+        Ok((input.config.shift, input.track.is_some(), signals.len()))
+    };
+
+    let first = bulk_process(record, &dir, &config, Shift::Found).expect("readable");
+    assert_eq!(first.len(), 1);
+    let (found, tracked, bursts) = first[0];
+    assert!((found - 300_000.).abs() < 300., "{found}");
+    assert!(tracked);
+    assert_eq!(bursts, 1);
+    assert!(dir.join("capture.cf32.sigmf-meta").is_file());
+
+    // the meta isn't a capture, and its shift is used instead of looking again
+    let second = bulk_process(record, &dir, &config, Shift::Found).expect("readable");
+    assert_eq!(second, vec![(found, false, 1)]);
+
+    // unless we're told otherwise, which isn't recorded
+    let given = bulk_process(record, &dir, &config, Shift::Given).expect("readable");
+    assert_eq!((given[0].0, given[0].1), (476_000., false));
+    let third = bulk_process(record, &dir, &config, Shift::Found).expect("readable");
+    fs::remove_dir_all(&dir).expect("temp dir");
+    assert_eq!(third, vec![(found, false, 1)]);
+}
+
+#[test]
+fn test_foreign_recording() {
+    use crate::sigmf::burst_annotation;
+
+    let dir = std::env::temp_dir().join(format!("shinelink-foreign-{}", std::process::id()));
+    fs::create_dir_all(&dir).expect("temp dir");
+    write_capture(&dir.join("theirs.sigmf-data"), -476_000.);
+    let theirs = r#"{
+        "global": {
+            "core:datatype": "cf32_le",
+            "core:sample_rate": 2880000.0,
+            "core:version": "1.0.0",
+            "core:author": "someone"
+        },
+        "captures": [{"core:sample_start": 0}],
+        "annotations": [{"core:sample_start": 0, "core:label": "theirs"}]
+    }"#;
+    fs::write(dir.join("theirs.sigmf-meta"), theirs).expect("temp file");
+
+    let config = Config {
+        decimation: 16,
+        sample_rate: 2_880_000,
+        deviation: 60_000,
+        shift: 476_000.,
+        filter: None,
+        energy: None,
+    };
+    // as perfect-packets annotates each burst
+    let record = |input: &Input, signals: &[Signal]| -> Result<_> {
+        let annotations = signals
+            .iter()
+            .map(|signal| burst_annotation(&input.config, signal))
+            .collect();
+        save_annotations(input, annotations)
+    };
+    for _ in 0..2 {
+        let written = bulk_process(record, &dir, &config, Shift::Recorded).expect("readable");
+        assert_eq!(written, vec![dir.join("theirs.shinelink.sigmf-meta")]);
+    }
+
+    // their meta is untouched; ours keeps what they said, and their annotation
+    let untouched = fs::read_to_string(dir.join("theirs.sigmf-meta")).expect("readable");
+    let sidecar = fs::read_to_string(dir.join("theirs.shinelink.sigmf-meta")).expect("readable");
+    fs::remove_dir_all(&dir).expect("temp dir");
+    assert_eq!(untouched, theirs);
+    assert!(sidecar.contains(r#""core:author": "someone""#), "{sidecar}");
+    assert!(
+        sidecar.contains(r#""core:dataset": "theirs.sigmf-data""#),
+        "{sidecar}"
+    );
+    let sidecar: Meta = serde_json::from_str(&sidecar).expect("parseable");
+    assert_eq!(sidecar.annotations.len(), 2, "{sidecar:?}");
+    assert_eq!(sidecar.annotations[0].label.as_deref(), Some("theirs"));
+}
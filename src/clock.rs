@@ -0,0 +1,203 @@
+//! symbol timing recovery, which follows the transmitter's clock through a packet
+//!
+//! The alternative, `decode::recover_bytes`, tries hundreds of fixed clocks, and can't follow
+//! a clock which wanders, or jumps, part way through a packet.
+
+use crate::decode::DISCONTINUITY;
+
+/// how hard a timing error pulls the next sample point, in samples per unit of error per bit
+const PHASE_GAIN: f32 = 0.1;
+/// ...and the bit period
+const PERIOD_GAIN: f32 = 0.01;
+/// if the period wanders further than this from nominal, we've lost it, so start again
+const MAX_PERIOD_ERROR: f32 = 0.15;
+
+/// Gardner's timing error detector, in a proportional-integral loop, on a normalised
+/// (roughly -1 to 1) demodulated stream
+///
+/// Returns soft bits: the sign is the bit (one is positive), and the magnitude is how sure we
+/// are, roughly 0 to 1; and the bit period it settled on, by the end.
+///
+/// After a click (see `Diagnostics::discontinuities`), the timing may have jumped, so rather
+/// than slowly pulling in, we start again from the first edge after it.
+pub fn gardner(input: &[f32], samples_per_bit: f32) -> (Vec<f32>, f32) {
+    let mut clicks = clicks(input).into_iter().peekable();
+    let smoothing = samples_per_bit / 2.;
+    let input = &smooth(input, smoothing);
+    let nominal = samples_per_bit;
+    let mut period = nominal;
+    let mut pos = nominal / 2.;
+    let mut prev: Option<f32> = None;
+    // where `prev` was taken
+    let mut last = pos - period;
+
+    let mut soft = Vec::with_capacity((input.len() as f32 / nominal) as usize + 1);
+    while pos + 1. < input.len() as f32 {
+        if let Some((_, end)) = clicks.next_if(|&(start, _)| start as f32 <= pos) {
+            // the smoothing smears the click this far
+            let after = end + (smoothing / 2.).ceil() as usize + 1;
+            let Some(edge) = (after..input.len())
+                .find(|&i| input[i].is_sign_positive() != input[i - 1].is_sign_positive())
+            else {
+                break;
+            };
+            // the strobe after the last one, in step with the edge; if the click didn't move the
+            // timing, this is where we were going anyway
+            let bits = ((edge as f32 - last) / period).floor();
+            pos = edge as f32 + period / 2. - bits * period;
+            prev = None;
+            continue;
+        }
+
+        let strobe = interpolate(input, pos);
+        last = pos;
+        if let Some(prev) = prev {
+            // between two bits, the signal should be crossing zero, if they differ; if it's
+            // already on the new bit's side, we're late, if it's still on the old bit's, early
+            let mid = interpolate(input, pos - period / 2.);
+            let error = (mid * (prev - strobe)).clamp(-2., 2.);
+            pos += PHASE_GAIN * nominal * error;
+            period += PERIOD_GAIN * nominal * error;
+
+            if (period - nominal).abs() > MAX_PERIOD_ERROR * nominal {
+                period = nominal;
+            }
+        }
+
+        soft.push(strobe.clamp(-1., 1.));
+        prev = Some(strobe);
+        pos += period;
+    }
+
+    (soft, period)
+}
+
+/// the first and last sample of each run of samples beyond `DISCONTINUITY`
+fn clicks(input: &[f32]) -> Vec<(usize, usize)> {
+    let mut clicks: Vec<(usize, usize)> = Vec::new();
+    for (i, v) in input.iter().enumerate() {
+        if v.abs() <= DISCONTINUITY {
+            continue;
+        }
+        match clicks.last_mut() {
+            Some((_, end)) if *end + 1 == i => *end = i,
+            _ => clicks.push((i, i)),
+        }
+    }
+    clicks
+}
+
+/// a centred moving average, e.g. half a bit long, so square edges become ramps, and the signal
+/// at the midpoint says how far we are from the crossing, not just which side we're on
+///
+/// Any longer, and the already-smooth real captures (GFSK?) smear into their neighbours.
+fn smooth(input: &[f32], len: f32) -> Vec<f32> {
+    let len = (len.round() as usize).max(1);
+    let half = len / 2;
+    let mut sums = Vec::with_capacity(input.len() + 1);
+    sums.push(0f64);
+    for &v in input {
+        sums.push(sums.last().expect("non-empty") + f64::from(v));
+    }
+
+    (0..input.len())
+        .map(|i| {
+            let start = i.saturating_sub(half);
+            let end = (start + len).min(input.len());
+            ((sums[end] - sums[start]) / (end - start) as f64) as f32
+        })
+        .collect()
+}
+
+/// linear, which is plenty at ~18 samples per bit
+fn interpolate(input: &[f32], pos: f32) -> f32 {
+    let pos = pos.max(0.);
+    let i = pos as usize;
+    let frac = pos - i as f32;
+    match (input.get(i), input.get(i + 1)) {
+        (Some(a), Some(b)) => a + (b - a) * frac,
+        (Some(a), None) => *a,
+        _ => 0.,
+    }
+}
+
+#[cfg(test)]
+fn nrz(bits: &[bool], samples_per_bit: f32) -> Vec<f32> {
+    let len = (bits.len() as f32 * samples_per_bit) as usize;
+    (0..len)
+        .map(|i| bits[(i as f32 / samples_per_bit) as usize])
+        .map(|bit| if bit { 1. } else { -1. })
+        .collect()
+}
+
+#[test]
+fn test_gardner_follows_a_slow_clock() {
+    let bits = (0..400u32)
+        .map(|i| i < 40 && i % 2 == 0 || i.wrapping_mul(2_654_435_761) >> 31 == 1)
+        .collect::<Vec<bool>>();
+
+    // 4% slower than we think; a fixed clock would be a whole bit out after 25 bits
+    let input = nrz(&bits, 18. * 1.04);
+    let (soft, period) = gardner(&input, 18.);
+    let recovered = soft.iter().map(|&v| v > 0.).collect::<Vec<bool>>();
+
+    // after the preamble settles it, every bit is right, and confident
+    assert!(recovered.len() >= bits.len() - 1, "{}", recovered.len());
+    assert_eq!(recovered[40..bits.len() - 1], bits[40..bits.len() - 1]);
+    assert!(soft[40..bits.len() - 1].iter().all(|v| v.abs() > 0.5));
+    assert!((period - 18. * 1.04).abs() < 0.2, "{period}");
+}
+
+#[test]
+fn test_gardner_resyncs_after_a_click() {
+    let bits = (0..400u32)
+        .map(|i| i < 40 && i % 2 == 0 || i.wrapping_mul(2_654_435_761) >> 31 == 1)
+        .collect::<Vec<bool>>();
+
+    // a click, which moves the rest of the packet by half a bit
+    let mut input = nrz(&bits, 18.);
+    let at = 200 * 18 + 3;
+    input.splice(at..at, [6.; 9]);
+    let (soft, _) = gardner(&input, 18.);
+    let recovered = soft.iter().map(|&v| v > 0.).collect::<Vec<bool>>();
+
+    // every bit before the click is right, and every bit from the one after it is right, and
+    // confident, though the bit it landed in may be lost, or read twice; without starting
+    // again, the loop takes a few bits to pull in, and is unsure of them
+    assert_eq!(recovered[..199], bits[..199]);
+    let after = &bits[201..bits.len() - 1];
+    assert!(
+        (200..=202).any(|from| recovered[from..].starts_with(after)
+            && soft[from..from + after.len()].iter().all(|v| v.abs() > 0.5)),
+        "{:?}",
+        &soft[195..215]
+    );
+}
+
+#[test]
+fn test_gardner_one_chat() {
+    use crate::decode::{self, Clock, decode_with};
+    use crate::listen::{one_chat_outbound, quiet_noise};
+    use crate::squelch::{Config, squelch_source};
+
+    let mut samples = quiet_noise(640_000, 1);
+    samples.extend(one_chat_outbound());
+    samples.extend(quiet_noise(640_000, 2));
+    let config = Config {
+        decimation: 16,
+        sample_rate: 3_200_000,
+        deviation: 60_000,
+        shift: 257_000.,
+        filter: None,
+        energy: None,
+    };
+    let bursts = squelch_source(&mut samples.as_slice(), &config).expect("in-memory");
+    assert_eq!(bursts.len(), 1);
+
+    let decoder = decode::Config {
+        clock: Clock::Gardner,
+        ..decode::Config::default()
+    };
+    let decoded = decode_with(&bursts[0].fm, 20., &decoder);
+    assert_eq!(decoded.matches_crc.len(), 1, "{decoded:?}");
+}
@@ -0,0 +1,200 @@
+//! vote across repeated copies of a packet, none of which need decode on their own
+//!
+//! The ShineLink sometimes repeats its response, "for reliability". Each copy is damaged in
+//! different places, so where most copies agree on a bit, that's probably the bit.
+//!
+//! Copies are matched on their header (serials, sequence number and packet type), which comes
+//! first, and so usually survives; a copy with a damaged header is left out.
+
+use crate::bits_to_byte;
+use crate::decode::{Candidates, decrypt_aligned};
+use crate::encode::encrypt;
+use crate::frame::Frame;
+use itertools::Itertools;
+
+/// even a bit we're unsure of gets a vote
+const MIN_WEIGHT: f32 = 0.05;
+
+/// the `candidates` from one burst, e.g. from `squelch`
+pub struct Burst {
+    /// in whatever units the caller likes, e.g. the chunk numbers from `squelch`
+    pub offset: usize,
+    pub candidates: Candidates,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Combined {
+    /// of the first copy
+    pub offset: usize,
+    /// how many bursts were voted across
+    pub copies: usize,
+    /// the decrypted frame, without its checksum, as `Decoded::matches_crc`
+    pub frame: Vec<u8>,
+}
+
+#[derive(PartialEq, Eq)]
+struct Key {
+    len: u8,
+    seq: u8,
+    lanbox_serial: String,
+    link_serial: String,
+    packet_type: u16,
+}
+
+struct Group<'b> {
+    key: Key,
+    offset: usize,
+    /// (burst index, candidate, confidence)
+    copies: Vec<(usize, &'b [u8], &'b [f32])>,
+}
+
+/// Group bursts with the same header which start within `window` of the group's first, and
+/// vote on each bit; returns the groups of two or more which then match their checksum.
+pub fn combine(bursts: &[Burst], window: usize) -> Vec<Combined> {
+    let mut groups: Vec<Group> = Vec::new();
+    for (i, burst) in bursts
+        .iter()
+        .enumerate()
+        .sorted_by_key(|(_, burst)| burst.offset)
+    {
+        // the most confident candidate with each header; a sweep finds many
+        let best = burst
+            .candidates
+            .iter()
+            .filter_map(|(cand, found)| Some((header(cand)?, cand, &found.confidence)))
+            .into_group_map_by(|(key, _, _)| (key.len, key.seq, key.packet_type))
+            .into_values()
+            .filter_map(|same| {
+                same.into_iter()
+                    .max_by(|a, b| a.2.iter().sum::<f32>().total_cmp(&b.2.iter().sum()))
+            });
+
+        for (key, cand, confidence) in best {
+            let copy = (i, cand.as_slice(), confidence.as_slice());
+            match groups.iter_mut().find(|g| {
+                g.key == key
+                    && burst.offset - g.offset <= window
+                    && g.copies.iter().all(|&(j, _, _)| j != i)
+            }) {
+                Some(group) => group.copies.push(copy),
+                None => groups.push(Group {
+                    key,
+                    offset: burst.offset,
+                    copies: vec![copy],
+                }),
+            }
+        }
+    }
+
+    groups
+        .into_iter()
+        .filter(|g| g.copies.len() > 1)
+        .filter_map(|g| {
+            let frame = decrypt_aligned(&vote(&g.copies, usize::from(g.key.len)))?;
+            Some(Combined {
+                offset: g.offset,
+                copies: g.copies.len(),
+                frame,
+            })
+        })
+        .collect()
+}
+
+/// what the start of a candidate claims to be, if it's readable, and long enough to vote with
+fn header(cand: &[u8]) -> Option<Key> {
+    let (&len, rest) = cand.split_first()?;
+    // a copy cut off before its checksum can't vote on it
+    if rest.len() < usize::from(len) {
+        return None;
+    }
+    let frame_len = usize::from(len).checked_sub(2)?;
+    let plain = encrypt(rest.get(..frame_len)?);
+    let frame = Frame::parse(&plain).ok()?;
+    Some(Key {
+        len,
+        seq: frame.seq,
+        lanbox_serial: frame.lanbox_serial,
+        link_serial: frame.link_serial,
+        packet_type: frame.packet_type,
+    })
+}
+
+/// the length byte and `len` bytes after it, by confidence-weighted majority
+fn vote(copies: &[(usize, &[u8], &[f32])], len: usize) -> Vec<u8> {
+    let bits = (0..(1 + len) * 8)
+        .map(|i| {
+            copies
+                .iter()
+                .map(|(_, cand, confidence)| {
+                    let weight = confidence.get(i).copied().unwrap_or(0.).max(MIN_WEIGHT);
+                    if cand[i / 8] >> (7 - i % 8) & 1 == 1 {
+                        weight
+                    } else {
+                        -weight
+                    }
+                })
+                .sum::<f32>()
+                > 0.
+        })
+        .collect_vec();
+    bits.chunks_exact(8).map(bits_to_byte).collect()
+}
+
+#[test]
+fn test_combine() {
+    use crate::decode::Candidate;
+    use crate::encode::{PREAMBLE, encode};
+    use std::collections::HashMap;
+
+    let frame = Frame::parse(b"RF9\x01\x00\x10KWK1CGQ11AHZL0CGQ11A\x03\x00\x04\x00\x01\x02\x03")
+        .expect("valid frame");
+    let after_sync = encode(&frame)[PREAMBLE.len() + 4..].to_vec();
+
+    // three copies, each with a different byte of the payload damaged
+    let burst = |offset: usize, damaged: usize| {
+        let mut cand = after_sync.clone();
+        cand[damaged] ^= 0b0101_0000;
+        let confidence = vec![1.; cand.len() * 8];
+        assert_eq!(decrypt_aligned(&cand), None);
+        Burst {
+            offset,
+            candidates: HashMap::from([(
+                cand,
+                Candidate {
+                    confidence,
+                    bit_period: 18.,
+                },
+            )]),
+        }
+    };
+
+    let combined = combine(&[burst(100, 30), burst(110, 31), burst(120, 32)], 50);
+    assert_eq!(
+        combined,
+        vec![Combined {
+            offset: 100,
+            copies: 3,
+            frame: frame.to_plain_bytes(),
+        }]
+    );
+
+    // a copy cut off before its checksum is left out, rather than voting with bits it hasn't got
+    let mut truncated = burst(115, 33);
+    truncated.candidates = truncated
+        .candidates
+        .into_iter()
+        .map(|(mut cand, found)| {
+            cand.truncate(cand.len() - 2);
+            (cand, found)
+        })
+        .collect();
+    let combined = combine(
+        &[burst(100, 30), burst(110, 31), truncated, burst(120, 32)],
+        50,
+    );
+    assert_eq!(combined.len(), 1);
+    assert_eq!(combined[0].copies, 3);
+
+    // too far apart to be repeats, and two copies alone can't outvote each other
+    assert!(combine(&[burst(100, 30), burst(200, 31), burst(210, 32)], 50).is_empty());
+}
@@ -14,9 +14,22 @@ pub fn crc_suffixed(data: &[u8]) -> Option<&[u8]> {
     }
 }
 
+/// append the big-endian checksum, the inverse of `crc_suffixed`
+pub fn crc_append(mut data: Vec<u8>) -> Vec<u8> {
+    let checksum = MODBUS.checksum(&data);
+    data.extend_from_slice(&checksum.to_be_bytes());
+    data
+}
+
 #[test]
 fn test_crc() {
     // the library has completely changed their api again, haven't they. hth hand
     assert_eq!(MODBUS.checksum(b"123456789"), 19255);
     assert_eq!(MODBUS.checksum(b"12345678"), 14301);
 }
+
+#[test]
+fn test_crc_append() {
+    let appended = crc_append(b"123456789".to_vec());
+    assert_eq!(crc_suffixed(&appended), Some(&b"123456789"[..]));
+}
@@ -1,21 +1,334 @@
 use crate::bits_to_byte;
+use crate::clock::gardner;
 use crate::crc::crc_suffixed;
+use crate::encode::encrypt;
+use crate::frame::Frame;
+use crate::known::{Known, after_template};
+use crate::repair::repair;
+use crate::slip::realign;
+use crate::squelch::Signal;
+use crate::sync::{SyncMatch, find_sync, slice};
 use itertools::Itertools;
 use memchr::memmem;
-use std::collections::HashSet;
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
 use std::f32::consts::PI;
+use std::str::FromStr;
 
-const KNOWN_HEADER_BYTES: &[u8; 4] = b"jack";
-const ENCRYPTION_KEY: &[u8; 10] = b"GROWATTRF.";
+pub(crate) const KNOWN_HEADER_BYTES: &[u8; 4] = b"jack";
+pub(crate) const ENCRYPTION_KEY: &[u8; 10] = b"GROWATTRF.";
+/// how far (in bits) a run can be from a whole number of bits before it's a timing error
+const TIMING_TOLERANCE: f32 = 0.2;
+/// normalised samples beyond this aren't signal, but a click, e.g. from a carrier phase jump
+pub(crate) const DISCONTINUITY: f32 = 3.;
+
+#[derive(Debug, Clone, Default)]
+pub struct Config {
+    /// if nothing decrypts at the aligned key phase, try every key offset and every length
+    pub brute_force: bool,
+    pub clock: Clock,
+    /// if nothing matches its checksum, try flipping up to this many of the least confident
+    /// bits, or adding or removing one of them; zero to not bother
+    pub repair_budget: usize,
+    /// if nothing matches its checksum, look for up to this many places where the bits slipped,
+    /// see `slip`; zero to not bother
+    pub max_slips: usize,
+    /// serials we expect to hear, to find frames even if "jack" is damaged, see `known`
+    pub known: Vec<Known>,
+    /// fill in `Decoded::diagnostics`, which takes another pass over the burst
+    pub diagnostics: bool,
+}
+
+/// how to find the bits in the demodulated signal
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Clock {
+    /// try every fixed clock near the expected one, on the edges we can find
+    #[default]
+    Sweep,
+    /// follow the clock through the packet, with `clock::gardner`
+    Gardner,
+    /// find the sync word in the samples, with `sync::find_sync`, and slice from there
+    Correlate,
+}
+
+impl FromStr for Clock {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Clock> {
+        Ok(match s {
+            "sweep" => Clock::Sweep,
+            "gardner" => Clock::Gardner,
+            "correlate" => Clock::Correlate,
+            other => anyhow::bail!(
+                "unknown clock recovery: {other:?}, expected sweep, gardner or correlate"
+            ),
+        })
+    }
+}
+
+/// how a checksum match was decrypted
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum KeyPhase {
+    /// the byte after the sync word is the (unencrypted) length, and the key starts on the next byte
+    Aligned,
+    /// found by trying every length, and every offset into the key (this one for the length byte)
+    BruteForce(usize),
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Decoded {
+    /// decrypted frames with a valid checksum (which has been removed), and how they were decrypted
+    pub matches_crc: HashMap<Vec<u8>, KeyPhase>,
+    /// decrypted streams which look interesting, but don't have a valid checksum
+    pub looks_plausible: HashSet<Vec<u8>>,
+    /// checksum matches which needed some bits changing first, and how many
+    pub repaired: HashMap<Vec<u8>, usize>,
+    /// checksum matches which needed realigning after some slips, and how many
+    pub slips: HashMap<Vec<u8>, usize>,
+    /// the bit period (samples) each checksum match was sliced at
+    pub clocks: HashMap<Vec<u8>, f32>,
+    /// how the burst looked, from `decode_with`, if `Config::diagnostics`; see `diagnose`
+    pub diagnostics: Option<Diagnostics>,
+}
+
+/// how a burst looked to the decoder, to tell why it failed
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Diagnostics {
+    /// the burst's power in the channel (dBFS), from the squelch, if the caller had its `Signal`;
+    /// see `with_signal`
+    pub rssi: Option<f32>,
+    /// ...and over the noise floor (dB)
+    pub channel_snr: Option<f32>,
+    /// of the demodulated signal, away from the edges: its level over the spread around it (dB)
+    pub snr: f32,
+    /// the mean bit period (samples), from the runs between edges
+    pub bit_period: f32,
+    /// of the bit period, run to run (samples²)
+    pub bit_period_variance: f32,
+    pub runs: usize,
+    /// runs more than `TIMING_TOLERANCE` of a bit from a whole number of bits
+    pub timing_errors: usize,
+    /// clicks, far outside the signal's levels, as a carrier phase jump demodulates to
+    pub discontinuities: usize,
+    /// the bit period (samples) the best match was sliced at: the sweep's clock, the sync word's,
+    /// or where `clock::gardner` settled; `None` if nothing matched
+    pub clock: Option<f32>,
+    /// of a frame which matched its checksum, if any did
+    pub key_phase: Option<KeyPhase>,
+    /// how many bits of that frame had to be changed first, see `Decoded::repaired`
+    pub repaired: usize,
+    /// ...or how many slips spliced out, see `Decoded::slips`
+    pub slips: usize,
+}
+
+impl Diagnostics {
+    /// with the power of the burst the squelch found, which the decoder never sees
+    pub fn with_signal(self, signal: &Signal) -> Diagnostics {
+        Diagnostics {
+            rssi: Some(signal.rssi),
+            channel_snr: Some(signal.snr),
+            ..self
+        }
+    }
+}
+
+/// the bytes after the sync word, and how they were found
+pub type Candidates = HashMap<Vec<u8>, Candidate>;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Candidate {
+    /// how sure we are of each bit, from 0 to 1
+    pub confidence: Vec<f32>,
+    /// the bit period (samples) the bits were sliced at
+    pub bit_period: f32,
+}
 
 pub fn decode(input: &[f32], edge_length: f32) -> (HashSet<Vec<u8>>, HashSet<Vec<u8>>) {
-    let differential = detect_edges(input, edge_length);
-    let runs = find_runs(&differential);
+    let decoded = decode_with(input, edge_length, &Config::default());
+    (
+        decoded.matches_crc.into_keys().collect(),
+        decoded.looks_plausible,
+    )
+}
 
-    let candidate_bytes = recover_bytes(&runs, edge_length);
+pub fn decode_with(input: &[f32], edge_length: f32, config: &Config) -> Decoded {
+    let mut decoded = decode_candidates(&candidates(input, edge_length, config), config);
+    if config.diagnostics {
+        decoded.diagnostics = Some(diagnose(input, edge_length, &decoded));
+    }
+    decoded
+}
 
-    let (matches_crc, looks_plausible) = attempt_decrypt(&candidate_bytes);
-    (matches_crc, looks_plausible)
+/// how the burst looked, and how it was decoded, for callers who use `decode_candidates`
+pub fn diagnose(input: &[f32], edge_length: f32, decoded: &Decoded) -> Diagnostics {
+    // the first run is from the start of the burst, not an edge
+    let runs = find_runs(&detect_edges(input, edge_length))
+        .into_iter()
+        .skip(1)
+        .map(|(len, _)| (len as f32, (len as f32 / edge_length).round()))
+        .filter(|&(_, bits)| bits > 0.)
+        .collect_vec();
+    let bits = runs.iter().map(|(_, bits)| bits).sum::<f32>();
+    let bit_period = if bits > 0. {
+        runs.iter().map(|(len, _)| len).sum::<f32>() / bits
+    } else {
+        edge_length
+    };
+    let bit_period_variance = if bits > 0. {
+        runs.iter()
+            .map(|(len, bits)| bits * (len / bits - bit_period).powi(2))
+            .sum::<f32>()
+            / bits
+    } else {
+        0.
+    };
+    let timing_errors = runs
+        .iter()
+        .filter(|(len, _)| {
+            let exact = len / bit_period;
+            (exact - exact.round()).abs() > TIMING_TOLERANCE
+        })
+        .count();
+
+    let discontinuities = input
+        .iter()
+        .map(|v| v.abs() > DISCONTINUITY)
+        .dedup()
+        .filter(|&click| click)
+        .count();
+
+    let best = decoded
+        .matches_crc
+        .iter()
+        .min_by_key(|(_, phase)| match phase {
+            KeyPhase::Aligned => 0,
+            KeyPhase::BruteForce(offset) => 1 + offset,
+        });
+
+    Diagnostics {
+        rssi: None,
+        channel_snr: None,
+        snr: level_snr(input, edge_length),
+        bit_period,
+        bit_period_variance,
+        runs: runs.len(),
+        timing_errors,
+        discontinuities,
+        clock: best.and_then(|(frame, _)| decoded.clocks.get(frame).copied()),
+        key_phase: best.map(|(_, &phase)| phase),
+        repaired: best
+            .and_then(|(frame, _)| decoded.repaired.get(frame).copied())
+            .unwrap_or(0),
+        slips: best
+            .and_then(|(frame, _)| decoded.slips.get(frame).copied())
+            .unwrap_or(0),
+    }
+}
+
+/// of a normalised (roughly -1 to 1) burst: the mean level over the spread around it (dB), in
+/// the middle of each bit, so the transitions aren't counted as noise
+fn level_snr(input: &[f32], edge_length: f32) -> f32 {
+    let half = (edge_length / 2.).round() as usize;
+    let middles = input
+        .windows(2 * half + 1)
+        .filter(|w| w.iter().all(|v| v.signum() == w[0].signum()))
+        .map(|w| w[half].abs())
+        .filter(|v| *v <= DISCONTINUITY)
+        .collect_vec();
+    if middles.is_empty() {
+        return 0.;
+    }
+    let level = middles.iter().sum::<f32>() / middles.len() as f32;
+    let spread = middles.iter().map(|v| (v - level).powi(2)).sum::<f32>() / middles.len() as f32;
+    10. * (level * level / spread.max(1e-9)).log10()
+}
+
+/// the possible bit streams in a burst, before any decryption
+pub fn candidates(input: &[f32], edge_length: f32, config: &Config) -> Candidates {
+    match config.clock {
+        Clock::Sweep => {
+            let differential = detect_edges(input, edge_length);
+            let runs = find_runs(&differential);
+            recover_bytes(&runs, edge_length, &config.known)
+        }
+        Clock::Gardner => {
+            let (soft, period) = gardner(input, edge_length);
+            let bits = soft.iter().map(|&v| v > 0.).collect_vec();
+            let confidence = soft.iter().map(|v| v.abs()).collect_vec();
+            let mut candidate_bytes = HashMap::with_capacity(1);
+            after_sync(&bits, &confidence, period, &mut candidate_bytes);
+            after_template(
+                &bits,
+                &confidence,
+                period,
+                &config.known,
+                &mut candidate_bytes,
+            );
+            candidate_bytes
+        }
+        Clock::Correlate => {
+            let mut candidate_bytes = HashMap::with_capacity(8);
+            let Some(sync) = find_sync(input, edge_length) else {
+                return candidate_bytes;
+            };
+            // the template is too short to pin the period down for a whole long packet
+            for fine in -15..=15 {
+                let sync = SyncMatch {
+                    samples_per_bit: sync.samples_per_bit + fine as f32 / 100.,
+                    ..sync
+                };
+                let (bits, confidence) = slice(input, &sync);
+                let bytes = bits.chunks_exact(8).map(bits_to_byte).collect_vec();
+                candidate_bytes.entry(bytes).or_insert(Candidate {
+                    confidence,
+                    bit_period: sync.samples_per_bit,
+                });
+            }
+            candidate_bytes
+        }
+    }
+}
+
+/// the second half of `decode_with`, for callers who want the `candidates` too
+pub fn decode_candidates(candidate_bytes: &Candidates, config: &Config) -> Decoded {
+    let mut decoded = attempt_decrypt_aligned(candidate_bytes);
+    // with this many guesses, the checksum alone will eventually match something
+    let believable = |cand: &[u8]| {
+        decrypt_aligned(cand).filter(|frame| {
+            Frame::parse(frame).is_ok_and(|frame| {
+                config.known.is_empty() || config.known.iter().any(|k| k.matches(&frame))
+            })
+        })
+    };
+    if decoded.matches_crc.is_empty() && config.repair_budget > 0 {
+        for (cand, found) in candidate_bytes {
+            if let Some((frame, edits)) =
+                repair(cand, &found.confidence, config.repair_budget, believable)
+            {
+                decoded.matches_crc.insert(frame.clone(), KeyPhase::Aligned);
+                decoded.clocks.insert(frame.clone(), found.bit_period);
+                decoded.repaired.insert(frame, edits);
+            }
+        }
+    }
+    if decoded.matches_crc.is_empty() && config.max_slips > 0 {
+        for (cand, found) in candidate_bytes {
+            if let Some((frame, slips)) = realign(cand, config.max_slips, believable) {
+                decoded.matches_crc.insert(frame.clone(), KeyPhase::Aligned);
+                decoded.clocks.insert(frame.clone(), found.bit_period);
+                decoded.slips.insert(frame, slips);
+            }
+        }
+    }
+    if decoded.matches_crc.is_empty() && config.brute_force {
+        let brute = attempt_decrypt(candidate_bytes);
+        decoded.matches_crc = brute.matches_crc;
+        decoded.clocks = brute.clocks;
+        decoded.looks_plausible.extend(brute.looks_plausible);
+    }
+    decoded
 }
 
 /// detect edges in a "time domain" signal, outputting how close we are to a positive or negative edge
@@ -102,49 +415,116 @@ fn find_runs(differential: &[f32]) -> Vec<(usize, bool)> {
 
 /// given a bunch of pulse lengths, and a bit length, find some clocks and offsets of bits
 /// which result in byte streams which contain the known header bytes
-fn recover_bytes(runs: &[(usize, bool)], edge_length: f32) -> HashSet<Vec<u8>> {
-    let mut candidate_bytes = HashSet::with_capacity(4);
+fn recover_bytes(runs: &[(usize, bool)], edge_length: f32, known: &[Known]) -> Candidates {
+    let mut candidate_bytes = HashMap::with_capacity(4);
+    // the first and last clocks which found each candidate; it's reported at the middle
+    let mut clocks: HashMap<Vec<u8>, (f32, f32)> = HashMap::with_capacity(4);
 
     // e.g. 15.00, 15.01,.. to 21.00
     for clock in (-300..300).map(|v| edge_length + (v as f32) / 100.) {
         let mut bits = Vec::with_capacity(runs.len() * 6);
+        let mut confidence = Vec::with_capacity(runs.len() * 6);
         for (run_length, is_positive) in runs {
-            for _ in 0..(*run_length as f32 / clock).round() as usize {
+            let exact = *run_length as f32 / clock;
+            let count = exact.round() as usize;
+            for i in 0..count {
                 // TODO: suspicious bang
                 bits.push(!*is_positive);
+                // the last bit of a run might really be the first bit of the next, if the
+                // run was half a bit out
+                confidence.push(if i + 1 == count {
+                    1. - 2. * (exact - count as f32).abs()
+                } else {
+                    1.
+                });
             }
         }
 
-        if bits.len() < 32 {
-            continue;
+        let mut found = HashMap::new();
+        after_sync(&bits, &confidence, clock, &mut found);
+        after_template(&bits, &confidence, clock, known, &mut found);
+        for (cand, candidate) in found {
+            clocks
+                .entry(cand.clone())
+                .and_modify(|(_, last)| *last = clock)
+                .or_insert((clock, clock));
+            candidate_bytes.entry(cand).or_insert(candidate);
         }
+    }
 
-        for offset in 0..8 {
-            let cand = bits[offset..]
-                .chunks_exact(8)
-                .map(bits_to_byte)
-                .collect_vec();
+    for (cand, candidate) in &mut candidate_bytes {
+        let (first, last) = clocks[cand];
+        candidate.bit_period = (first + last) / 2.;
+    }
+    candidate_bytes
+}
 
-            let header = KNOWN_HEADER_BYTES;
-            if let Some(jack) = memmem::find(&cand, header) {
-                candidate_bytes.insert(cand[jack + header.len()..].to_vec());
-            }
+/// the bytes after the sync word, at whichever bit offset it's found
+fn after_sync(
+    bits: &[bool],
+    confidence: &[f32],
+    bit_period: f32,
+    candidate_bytes: &mut Candidates,
+) {
+    if bits.len() < 32 {
+        return;
+    }
+
+    for offset in 0..8 {
+        let cand = bits[offset..]
+            .chunks_exact(8)
+            .map(bits_to_byte)
+            .collect_vec();
+
+        let header = KNOWN_HEADER_BYTES;
+        if let Some(jack) = memmem::find(&cand, header) {
+            let start = jack + header.len();
+            let bit_start = offset + start * 8;
+            candidate_bytes
+                .entry(cand[start..].to_vec())
+                .or_insert_with(|| Candidate {
+                    confidence: confidence[bit_start..offset + cand.len() * 8].to_vec(),
+                    bit_period,
+                });
         }
     }
+}
 
-    candidate_bytes
+/// decrypt the candidates as the radio framed them: a length byte, then the encrypted frame,
+/// with the key starting on the first byte of the frame (see one-chat's SPI traces)
+fn attempt_decrypt_aligned(candidate_bytes: &Candidates) -> Decoded {
+    let mut decoded = Decoded::default();
+    for (cand, found) in candidate_bytes {
+        if let Some(frame) = decrypt_aligned(cand) {
+            decoded.matches_crc.insert(frame.clone(), KeyPhase::Aligned);
+            decoded.clocks.insert(frame, found.bit_period);
+        }
+
+        let decrypted = encrypt(cand.get(1..).unwrap_or_default());
+        if likely_valid(&decrypted) {
+            decoded.looks_plausible.insert(decrypted);
+        }
+    }
+
+    decoded
+}
+
+/// the frame, without its checksum, if the checksum matches
+pub(crate) fn decrypt_aligned(cand: &[u8]) -> Option<Vec<u8>> {
+    let (&len, rest) = cand.split_first()?;
+    let decrypted = encrypt(rest.get(..usize::from(len))?);
+    crc_suffixed(&decrypted).map(|v| v.to_vec())
 }
 
 /// classify the candidates by whether we can decrypt them to strings matching the crc,
 /// and whether they look plausible after some decryption
 ///
 /// note that, on bit alignment errors, the second half of the packet may decrypt with a different offset.
-fn attempt_decrypt(candidate_bytes: &HashSet<Vec<u8>>) -> (HashSet<Vec<u8>>, HashSet<Vec<u8>>) {
+fn attempt_decrypt(candidate_bytes: &Candidates) -> Decoded {
     let key = ENCRYPTION_KEY;
 
-    let mut matches_crc = HashSet::with_capacity(4);
-    let mut looks_plausible = HashSet::with_capacity(16);
-    for cand in candidate_bytes {
+    let mut decoded = Decoded::default();
+    for (cand, found) in candidate_bytes {
         for offset in 0..key.len() {
             let decrypted = cand
                 .iter()
@@ -154,17 +534,21 @@ fn attempt_decrypt(candidate_bytes: &HashSet<Vec<u8>>) -> (HashSet<Vec<u8>>, Has
 
             for i in (1..decrypted.len()).rev() {
                 if let Some(crc_bytes) = crc_suffixed(&decrypted[1..i]) {
-                    matches_crc.insert(crc_bytes.to_vec());
+                    let frame = crc_bytes.to_vec();
+                    decoded.clocks.insert(frame.clone(), found.bit_period);
+                    decoded
+                        .matches_crc
+                        .insert(frame, KeyPhase::BruteForce(offset));
                 }
             }
 
             if likely_valid(&decrypted) {
-                looks_plausible.insert(decrypted.clone());
+                decoded.looks_plausible.insert(decrypted.clone());
             }
         }
     }
 
-    (matches_crc, looks_plausible)
+    decoded
 }
 
 /// if it contains the serial pair, or a long run of nulls, it's probably at least interesting
@@ -174,3 +558,251 @@ fn likely_valid(input: &[u8]) -> bool {
             .all(|v| v.is_ascii_digit() || v.is_ascii_uppercase())
     }) || input.windows(10).any(|w| w.iter().all(|&v| v == 0))
 }
+
+#[test]
+fn test_decrypt_aligned() {
+    use crate::encode::{PREAMBLE, frame_on_air};
+
+    let frame = b"RF9\x01\x00\x10KWK1CGQ11AHZL0CGQ11A\x03\x00\x01\x02".to_vec();
+    let mut on_air = frame_on_air(&crate::crc::crc_append(frame.clone()));
+    // trailing noise doesn't stop the length byte from finding the end
+    on_air.extend_from_slice(b"\x5e\x96\x29\xa5\x4e");
+
+    let after_sync = on_air[PREAMBLE.len() + KNOWN_HEADER_BYTES.len()..].to_vec();
+    let candidates = HashMap::from([(
+        after_sync.clone(),
+        Candidate {
+            confidence: vec![1.; after_sync.len() * 8],
+            bit_period: 18.,
+        },
+    )]);
+
+    let decoded = attempt_decrypt_aligned(&candidates);
+    assert_eq!(
+        decoded.matches_crc,
+        HashMap::from([(frame.clone(), KeyPhase::Aligned)])
+    );
+
+    let brute = attempt_decrypt(&candidates).matches_crc;
+    assert_eq!(
+        brute.get(&frame),
+        Some(&KeyPhase::BruteForce(ENCRYPTION_KEY.len() - 1))
+    );
+}
+
+#[test]
+fn test_repair_believable() {
+    use crate::crc::crc_append;
+    use crate::encode::{PREAMBLE, frame_on_air};
+
+    // one bit of each damaged, where we weren't sure of it
+    let damaged = |plain: &[u8]| {
+        let mut after_sync = frame_on_air(&crc_append(plain.to_vec()))
+            [PREAMBLE.len() + KNOWN_HEADER_BYTES.len()..]
+            .to_vec();
+        after_sync[20] ^= 0b0001_0000;
+        let mut confidence = vec![1.; after_sync.len() * 8];
+        confidence[20 * 8 + 3] = 0.1;
+        HashMap::from([(
+            after_sync,
+            Candidate {
+                confidence,
+                bit_period: 18.,
+            },
+        )])
+    };
+    let config = |known: &str| Config {
+        repair_budget: 1,
+        known: known
+            .split_terminator(',')
+            .map(|k| k.parse().expect("valid"))
+            .collect(),
+        ..Config::default()
+    };
+
+    let frame = b"RF9\x01\x00\x10KWK1CGQ11AHZL0CGQ11A\x03\x00\x04\x00\x01\x02\x03";
+    let decoded = decode_candidates(&damaged(frame), &config(""));
+    assert_eq!(decoded.repaired, HashMap::from([(frame.to_vec(), 1)]));
+    let decoded = decode_candidates(&damaged(frame), &config("KWK1CGQ11A/HZL0CGQ11A"));
+    assert_eq!(decoded.repaired.len(), 1);
+
+    // the checksum matches, but it's not a frame, or not one of ours
+    let not_a_frame = b"XX9\x01\x00\x10KWK1CGQ11AHZL0CGQ11A\x03\x00\x04\x00\x01\x02\x03";
+    assert!(
+        decode_candidates(&damaged(not_a_frame), &config(""))
+            .matches_crc
+            .is_empty()
+    );
+    let decoded = decode_candidates(&damaged(frame), &config("AAAAAAAAAA/BBBBBBBBBB"));
+    assert!(decoded.matches_crc.is_empty());
+}
+
+/// the IQ for `on_air`, as it would appear in a capture
+#[cfg(test)]
+pub(crate) fn synthesise(
+    on_air: &[u8],
+    config: &crate::modulate::Config,
+) -> Vec<num_complex::Complex32> {
+    // the last bit is only found if there's an edge after it; real captures have some mess here
+    let mut on_air = on_air.to_vec();
+    on_air.push(0xaa);
+    crate::modulate::modulate(&on_air, config)
+}
+
+/// squelch, then decode each burst, as if from a capture made with `config`
+#[cfg(test)]
+pub(crate) fn squelch_decode(
+    samples: &[num_complex::Complex32],
+    config: &crate::modulate::Config,
+    decoder: &self::Config,
+) -> Vec<Decoded> {
+    use crate::squelch::{Config, squelch_source};
+
+    let squelch_config = Config {
+        decimation: 16,
+        sample_rate: config.sample_rate,
+        deviation: 60_000,
+        shift: -config.offset,
+        filter: None,
+        energy: None,
+    };
+    let bursts = squelch_source(&mut &samples[..], &squelch_config).expect("in-memory");
+
+    let edge_length = config.sample_rate as f32 / 16. / config.bit_rate as f32;
+    bursts
+        .iter()
+        .map(|signal| {
+            let decoded = decode_with(&signal.fm, edge_length, decoder);
+            Decoded {
+                diagnostics: decoded.diagnostics.map(|d| d.with_signal(signal)),
+                ..decoded
+            }
+        })
+        .collect()
+}
+
+/// modulate, squelch, then decode, as if from a capture
+#[cfg(test)]
+pub(crate) fn round_trip(on_air: &[u8], config: &crate::modulate::Config) -> Decoded {
+    let mut decoded = squelch_decode(
+        &synthesise(on_air, config),
+        config,
+        &self::Config::default(),
+    );
+    assert_eq!(decoded.len(), 1, "one transmission");
+    decoded.remove(0)
+}
+
+#[test]
+fn test_decode_synthetic() {
+    use crate::encode::encode;
+    use crate::frame::Frame;
+    use crate::modulate;
+
+    let frame = Frame::parse(b"RF9\x01\x00\x10KWK1CGQ11AHZL0CGQ11A\x03\x00\x04\x00\x01\x02\x03")
+        .expect("valid frame");
+    let on_air = encode(&frame);
+
+    for (name, config) in [
+        ("fsk", modulate::Config::default()),
+        (
+            "gfsk, offset, noise",
+            modulate::Config {
+                sample_rate: 3_200_000,
+                offset: -257_000.,
+                bt: Some(0.5),
+                noise: 0.05,
+                seed: 1,
+                ..modulate::Config::default()
+            },
+        ),
+        (
+            "drift, phase jump",
+            modulate::Config {
+                offset: 476_000.,
+                drift: 20_000.,
+                phase_jumps: vec![(150_000, 1.)],
+                ..modulate::Config::default()
+            },
+        ),
+    ] {
+        let decoded = round_trip(&on_air, &config);
+        assert_eq!(decoded.diagnostics, None, "{name}: only if asked");
+        let frames = decoded
+            .matches_crc
+            .keys()
+            .map(|v| Frame::parse(v).expect("valid frame"))
+            .collect_vec();
+        assert_eq!(frames.len(), 1, "{name}: {frames:?}");
+        assert_eq!(frames[0], frame, "{name}");
+    }
+}
+
+#[test]
+fn test_diagnostics() {
+    use crate::encode::encode;
+    use crate::frame::Frame;
+    use crate::modulate;
+
+    let frame = Frame::parse(b"RF9\x01\x00\x10KWK1CGQ11AHZL0CGQ11A\x03\x00\x04\x00\x01\x02\x03")
+        .expect("valid frame");
+    let diagnose = |config: modulate::Config| {
+        let config = modulate::Config {
+            bt: Some(0.5),
+            ..config
+        };
+        let decoded = squelch_decode(
+            &synthesise(&encode(&frame), &config),
+            &config,
+            &Config {
+                diagnostics: true,
+                ..Config::default()
+            },
+        );
+        assert_eq!(decoded.len(), 1);
+        decoded[0].diagnostics.clone().expect("asked for")
+    };
+
+    let clean = diagnose(modulate::Config::default());
+    assert!((clean.bit_period - 18.).abs() < 0.1, "{clean:?}");
+    assert_eq!(clean.timing_errors, 0);
+    assert_eq!(clean.discontinuities, 0);
+    assert_eq!(clean.key_phase, Some(KeyPhase::Aligned));
+    assert_eq!((clean.repaired, clean.slips), (0, 0));
+    assert!(
+        clean.clock.is_some_and(|c| (c - 18.).abs() < 0.5),
+        "{clean:?}"
+    );
+    assert!(clean.snr > 20., "{clean:?}");
+    assert!(clean.rssi.is_some() && clean.channel_snr.is_some());
+
+    // a transmitter running fast
+    let fast = diagnose(modulate::Config {
+        bit_rate: 10_200,
+        ..modulate::Config::default()
+    });
+    assert!(
+        (fast.bit_period - 2_880_000. / 16. / 10_200.).abs() < 0.1,
+        "{fast:?}"
+    );
+    assert!(
+        fast.clock
+            .is_some_and(|c| (c - 2_880_000. / 16. / 10_200.).abs() < 0.3),
+        "{fast:?}"
+    );
+
+    let jump = diagnose(modulate::Config {
+        phase_jumps: vec![(150_000, 3.)],
+        ..modulate::Config::default()
+    });
+    assert_eq!(jump.discontinuities, 1, "{jump:?}");
+
+    let noisy = diagnose(modulate::Config {
+        noise: 0.25,
+        ..modulate::Config::default()
+    });
+    assert_eq!(noisy.key_phase, None);
+    assert_eq!(noisy.clock, None);
+    assert!(noisy.timing_errors > 10, "{noisy:?}");
+    assert!(noisy.snr < clean.snr - 10., "{noisy:?}");
+}
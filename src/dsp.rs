@@ -0,0 +1,234 @@
+//! the front end, before the samples are demodulated: mixing, filtering and decimating
+
+use num_complex::{Complex32, Complex64};
+use std::f64::consts::{PI, TAU};
+
+/// how often the `Nco` recomputes its phasor from scratch, in samples
+const RESYNC: usize = 4096;
+
+/// a numerically controlled oscillator, which shifts a signal up by `hz`
+///
+/// A rotator: each sample costs a complex multiply, not a `cos` and a `sin`. The rotator's
+/// rounding errors build up, so every `RESYNC` samples it's reset from a separate phase
+/// accumulator, which counts (fractions of) turns, so doesn't lose precision as it grows.
+pub struct Nco {
+    step: Complex64,
+    phasor: Complex64,
+    /// how far the phase moves between resyncs, ignoring whole turns, which are exact
+    turns_per_resync: f64,
+    /// at the last resync
+    turns: f64,
+    since: usize,
+}
+
+impl Nco {
+    pub fn new(hz: f64, sample_rate: u32) -> Nco {
+        let sample_rate = f64::from(sample_rate);
+        Nco {
+            step: Complex64::from_polar(1., TAU * hz / sample_rate),
+            phasor: Complex64::new(1., 0.),
+            turns_per_resync: (hz * RESYNC as f64).rem_euclid(sample_rate) / sample_rate,
+            turns: 0.,
+            since: 0,
+        }
+    }
+
+    /// the next sample, shifted
+    #[inline]
+    pub fn mix(&mut self, sample: Complex32) -> Complex32 {
+        self.advance();
+        sample * Complex32::new(self.phasor.re as f32, self.phasor.im as f32)
+    }
+
+    #[inline]
+    fn advance(&mut self) {
+        self.since += 1;
+        if self.since < RESYNC {
+            self.phasor *= self.step;
+            return;
+        }
+        self.since = 0;
+        self.turns = (self.turns + self.turns_per_resync) % 1.;
+        self.phasor = Complex64::from_polar(1., TAU * self.turns);
+    }
+}
+
+/// a windowed-sinc (Blackman) lowpass, with unity gain at DC; `taps` should be odd
+pub fn lowpass(cutoff: f64, sample_rate: f64, taps: usize) -> Vec<f32> {
+    assert!(taps > 0 && cutoff < sample_rate / 2.);
+    let fc = cutoff / sample_rate;
+    let mid = (taps - 1) as f64 / 2.;
+    let raw = (0..taps)
+        .map(|i| {
+            let t = i as f64 - mid;
+            let sinc = if t == 0. {
+                2. * fc
+            } else {
+                (TAU * fc * t).sin() / (PI * t)
+            };
+            let x = if taps == 1 {
+                0.5
+            } else {
+                i as f64 / (taps - 1) as f64
+            };
+            let window = 0.42 - 0.5 * (TAU * x).cos() + 0.08 * (2. * TAU * x).cos();
+            sinc * window
+        })
+        .collect::<Vec<f64>>();
+
+    let total = raw.iter().sum::<f64>();
+    raw.iter().map(|v| (v / total) as f32).collect()
+}
+
+/// an FIR filter which keeps one output in `factor`, and only computes those, as a polyphase
+/// decimator would
+pub struct Decimator {
+    /// reversed, so they line up with the history, oldest first
+    taps: Vec<f32>,
+    factor: usize,
+    /// the last `taps.len()` inputs, twice, so there's always a contiguous window of them
+    history: Vec<Complex32>,
+    pos: usize,
+    /// inputs since the last output
+    skipped: usize,
+}
+
+impl Decimator {
+    pub fn new(taps: &[f32], factor: usize) -> Decimator {
+        assert!(!taps.is_empty() && factor > 0);
+        Decimator {
+            taps: taps.iter().rev().copied().collect(),
+            factor,
+            history: vec![Complex32::new(0., 0.); 2 * taps.len()],
+            pos: 0,
+            skipped: 0,
+        }
+    }
+
+    /// the next output, if this input completes one
+    #[inline]
+    pub fn push(&mut self, sample: Complex32) -> Option<Complex32> {
+        let len = self.taps.len();
+        self.history[self.pos] = sample;
+        self.history[self.pos + len] = sample;
+        self.pos = (self.pos + 1) % len;
+
+        self.skipped += 1;
+        if self.skipped < self.factor {
+            return None;
+        }
+        self.skipped = 0;
+
+        let window = &self.history[self.pos..self.pos + len];
+        Some(
+            window
+                .iter()
+                .zip(&self.taps)
+                .fold(Complex32::new(0., 0.), |acc, (v, t)| acc + v * t),
+        )
+    }
+}
+
+/// the phase after `n` samples, exactly, for whole numbers of Hz
+#[cfg(test)]
+fn exact_phase(hz: u64, sample_rate: u64, n: u64) -> f64 {
+    let turns = u128::from(hz) * u128::from(n) % u128::from(sample_rate);
+    TAU * turns as f64 / sample_rate as f64
+}
+
+#[cfg(test)]
+fn phase_error(actual: f64, expected: f64) -> f64 {
+    let diff = (actual - expected).rem_euclid(TAU);
+    diff.min(TAU - diff)
+}
+
+#[test]
+fn test_nco() {
+    let mut nco = Nco::new(257_000., 3_200_000);
+    let mut worst = 0f64;
+    for n in 1..=3 * RESYNC as u64 + 17 {
+        let out = nco.mix(Complex32::new(1., 0.));
+        let error = phase_error(f64::from(out.arg()), exact_phase(257_000, 3_200_000, n));
+        worst = worst.max(error);
+        assert!((out.norm() - 1.).abs() < 1e-5);
+    }
+    assert!(worst < 1e-5, "{worst}");
+
+    // negative shifts go the other way
+    let mut down = Nco::new(-257_000., 3_200_000);
+    let out = down.mix(Complex32::new(1., 0.));
+    assert!((f64::from(out.arg()) + TAU * 257. / 3200.).abs() < 1e-6);
+}
+
+#[test]
+fn test_nco_hours() {
+    // ten hours at 3.2Msps: the rotator only runs between resyncs, so only the accumulator
+    // can drift, and it can be run on its own, a resync at a time
+    let mut nco = Nco::new(257_000., 3_200_000);
+    let samples = 10 * 3600 * 3_200_000u64;
+    for _ in 0..samples / RESYNC as u64 {
+        nco.since = RESYNC - 1;
+        nco.advance();
+    }
+    let expected = exact_phase(257_000, 3_200_000, samples / RESYNC as u64 * RESYNC as u64);
+    let error = phase_error(TAU * nco.turns, expected);
+    assert!(error < 1e-6, "{error}");
+
+    // and then carries on from there
+    for n in 1..RESYNC as u64 {
+        nco.advance();
+        let expected = exact_phase(
+            257_000,
+            3_200_000,
+            samples / RESYNC as u64 * RESYNC as u64 + n,
+        );
+        assert!(phase_error(nco.phasor.arg(), expected) < 1e-6);
+    }
+}
+
+#[test]
+fn test_lowpass() {
+    let taps = lowpass(70_000., 2_880_000., 127);
+    assert_eq!(taps.len(), 127);
+    assert!((taps.iter().sum::<f32>() - 1.).abs() < 1e-5);
+
+    let gain = |hz: f64| {
+        taps.iter()
+            .enumerate()
+            .map(|(i, &t)| Complex32::from_polar(t, (TAU * hz / 2_880_000. * i as f64) as f32))
+            .sum::<Complex32>()
+            .norm()
+    };
+    assert!(gain(20_000.) > 0.95, "{}", gain(20_000.));
+    assert!(gain(250_000.) < 0.01, "{}", gain(250_000.));
+}
+
+#[test]
+fn test_decimator() {
+    let taps = [0.5, 0.25, 0.125, 0.125];
+    let input = (0..20)
+        .map(|i| Complex32::new(i as f32, -(i as f32)))
+        .collect::<Vec<_>>();
+
+    let mut decimator = Decimator::new(&taps, 3);
+    let out = input
+        .iter()
+        .filter_map(|&v| decimator.push(v))
+        .collect::<Vec<_>>();
+
+    // the full convolution, then every third output, ending on the third input
+    let direct = (0..input.len())
+        .map(|n| {
+            (0..taps.len())
+                .filter(|&k| k <= n)
+                .map(|k| input[n - k] * taps[k])
+                .sum::<Complex32>()
+        })
+        .skip(2)
+        .step_by(3)
+        .collect::<Vec<_>>();
+    assert_eq!(out.len(), 6);
+    for (a, b) in out.iter().zip(&direct) {
+        assert!((a - b).norm() < 1e-4, "{a} != {b}");
+    }
+}
@@ -0,0 +1,66 @@
+use crate::decode::{ENCRYPTION_KEY, KNOWN_HEADER_BYTES};
+use crate::frame::Frame;
+
+/// alternating bits for the receiver to settle on; the Si4432's default preamble pattern
+pub const PREAMBLE: &[u8] = &[0xaa; 5];
+
+/// the byte stream the Si4432 would put on air for a frame:
+///
+/// ```text
+/// preamble, "jack", length, encrypt(frame, crc)
+/// ```
+///
+/// The length byte is (probably) added by the radio's packet handler, so is not encrypted.
+pub fn encode(frame: &Frame) -> Vec<u8> {
+    frame_on_air(&frame.to_bytes())
+}
+
+/// as `encode`, but for an arbitrary (already checksummed) payload
+pub fn frame_on_air(crc_suffixed: &[u8]) -> Vec<u8> {
+    let len = u8::try_from(crc_suffixed.len()).expect("payload must fit in the length byte");
+
+    let mut buf =
+        Vec::with_capacity(PREAMBLE.len() + KNOWN_HEADER_BYTES.len() + 1 + usize::from(len));
+    buf.extend_from_slice(PREAMBLE);
+    buf.extend_from_slice(KNOWN_HEADER_BYTES);
+    buf.push(len);
+    buf.extend(encrypt(crc_suffixed));
+    buf
+}
+
+/// xor with the key, starting at the start of the key; this is its own inverse
+///
+/// one-chat's SPI trace shows the first FIFO byte being xor'd with the 'G'.
+pub fn encrypt(plain: &[u8]) -> Vec<u8> {
+    plain
+        .iter()
+        .zip(ENCRYPTION_KEY.iter().cycle())
+        .map(|(&c, &k)| c ^ k)
+        .collect()
+}
+
+#[test]
+fn test_encrypt_one_chat() {
+    let plain = Frame::parse(b"RF9\x01\x00\x10KWK1CGQ11AHZL0CGQ11A\x03\x00\x00")
+        .expect("valid frame")
+        .to_bytes();
+
+    // the ShineLanBox's SPI write to the radio's FIFO, without the leading register address
+    let spi = hex::decode("1514765641441f050d1f04151e6670151c080a1e04151e6670155752463853")
+        .expect("valid hex");
+    assert_eq!(encrypt(&plain), spi);
+    assert_eq!(encrypt(&spi), plain);
+}
+
+#[test]
+fn test_encode_layout() {
+    let frame =
+        Frame::parse(b"RF9\x01\x00\x10KWK1CGQ11AHZL0CGQ11A\x03\x00\x01\x02").expect("valid frame");
+    let on_air = encode(&frame);
+
+    let body = &on_air[PREAMBLE.len() + KNOWN_HEADER_BYTES.len()..];
+    assert!(on_air.starts_with(PREAMBLE));
+    assert_eq!(&on_air[PREAMBLE.len()..][..4], KNOWN_HEADER_BYTES);
+    assert_eq!(usize::from(body[0]), body.len() - 1);
+    assert_eq!(Frame::parse_checked(&encrypt(&body[1..])), Ok(frame));
+}
@@ -0,0 +1,176 @@
+use crate::crc::{crc_append, crc_suffixed};
+use std::fmt;
+
+const MAGIC: &[u8; 2] = b"RF";
+const SERIAL_LEN: usize = 10;
+
+/// offset of the packet type field; everything before it is the fixed-size header
+const PACKET_TYPE_AT: usize = 27;
+/// offset of the payload, directly after the packet type
+pub(crate) const PAYLOAD_AT: usize = 29;
+
+/// a decrypted ShineLink frame, without its checksum
+///
+/// ```text
+///  0..2   "RF"
+///  2      sequence number
+///  3..6   version(?) bytes, [01] [00] [10] in every capture so far
+///  6..16  ShineLanBox serial, ascii
+/// 16..26  ShineLink serial, ascii
+/// 26      unknown, [03] in every capture so far
+/// 27..29  packet type, big endian
+/// 29..    payload
+/// ```
+///
+/// The packet type doesn't say how long the payload is; only the frame's length does.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Frame {
+    pub seq: u8,
+    pub version: [u8; 3],
+    /// ten characters, as are the ShineLink's
+    pub lanbox_serial: String,
+    pub link_serial: String,
+    pub unknown: u8,
+    pub packet_type: u16,
+    pub payload: Vec<u8>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ParseError {
+    /// the input was shorter than the fixed-size header
+    TooShort(usize),
+    /// the input didn't start with "RF"
+    BadMagic([u8; 2]),
+    /// a serial contained something other than ascii uppercase and digits
+    BadSerial(Vec<u8>),
+    /// the trailing CRC-16/MODBUS didn't match
+    BadChecksum,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParseError::TooShort(len) => {
+                write!(f, "frame too short: {len} < {PAYLOAD_AT} bytes")
+            }
+            ParseError::BadMagic(magic) => write!(f, "bad magic: {magic:02x?}"),
+            ParseError::BadSerial(serial) => {
+                write!(f, "bad serial: {}", crate::unambiguous(serial))
+            }
+            ParseError::BadChecksum => write!(f, "checksum mismatch"),
+        }
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+impl Frame {
+    /// parse a frame which has already had its checksum verified and removed, e.g. from `decode`
+    pub fn parse(data: &[u8]) -> Result<Frame, ParseError> {
+        if data.len() < PAYLOAD_AT {
+            return Err(ParseError::TooShort(data.len()));
+        }
+        if !data.starts_with(MAGIC) {
+            return Err(ParseError::BadMagic([data[0], data[1]]));
+        }
+
+        Ok(Frame {
+            seq: data[2],
+            version: [data[3], data[4], data[5]],
+            lanbox_serial: parse_serial(&data[6..6 + SERIAL_LEN])?,
+            link_serial: parse_serial(&data[6 + SERIAL_LEN..6 + 2 * SERIAL_LEN])?,
+            unknown: data[26],
+            packet_type: u16::from_be_bytes([data[PACKET_TYPE_AT], data[PACKET_TYPE_AT + 1]]),
+            payload: data[PAYLOAD_AT..].to_vec(),
+        })
+    }
+
+    /// parse a frame which still has its CRC-16/MODBUS trailer
+    pub fn parse_checked(data: &[u8]) -> Result<Frame, ParseError> {
+        Frame::parse(crc_suffixed(data).ok_or(ParseError::BadChecksum)?)
+    }
+
+    /// the plaintext bytes of the frame, without a checksum
+    pub fn to_plain_bytes(&self) -> Vec<u8> {
+        let mut buf = Vec::with_capacity(PAYLOAD_AT + self.payload.len() + 2);
+        buf.extend_from_slice(MAGIC);
+        buf.push(self.seq);
+        buf.extend_from_slice(&self.version);
+        buf.extend_from_slice(&serial_bytes(&self.lanbox_serial));
+        buf.extend_from_slice(&serial_bytes(&self.link_serial));
+        buf.push(self.unknown);
+        buf.extend_from_slice(&self.packet_type.to_be_bytes());
+        buf.extend_from_slice(&self.payload);
+        buf
+    }
+
+    /// the plaintext bytes of the frame, followed by the CRC-16/MODBUS checksum
+    pub fn to_bytes(&self) -> Vec<u8> {
+        crc_append(self.to_plain_bytes())
+    }
+}
+
+pub(crate) fn parse_serial(data: &[u8]) -> Result<String, ParseError> {
+    if !data
+        .iter()
+        .all(|v| v.is_ascii_uppercase() || v.is_ascii_digit())
+    {
+        return Err(ParseError::BadSerial(data.to_vec()));
+    }
+    Ok(String::from_utf8(data.to_vec()).expect("checked ascii"))
+}
+
+/// serials are fixed-width on the wire; pad (or truncate) whatever we've been handed
+fn serial_bytes(serial: &str) -> [u8; SERIAL_LEN] {
+    debug_assert_eq!(serial.len(), SERIAL_LEN, "serial {serial:?}");
+    let mut buf = [0u8; SERIAL_LEN];
+    for (b, s) in buf.iter_mut().zip(serial.bytes()) {
+        *b = s;
+    }
+    buf
+}
+
+#[test]
+fn test_one_chat_round_trip() {
+    // the uart dump of the ShineLanBox's request, from one-chat/README.md
+    let mut wire = b"RF9\x01\x00\x10KWK1CGQ11AHZL0CGQ11A\x03\x00\x00".to_vec();
+    wire.extend_from_slice(&[0x16, 0x14]);
+
+    let frame = Frame::parse_checked(&wire).expect("valid frame");
+    assert_eq!(
+        frame,
+        Frame {
+            seq: b'9',
+            version: [1, 0, 0x10],
+            lanbox_serial: "KWK1CGQ11A".to_string(),
+            link_serial: "HZL0CGQ11A".to_string(),
+            unknown: 3,
+            packet_type: 0,
+            payload: Vec::new(),
+        }
+    );
+    assert_eq!(frame.to_bytes(), wire);
+}
+
+#[test]
+fn test_parse_errors() {
+    assert_eq!(Frame::parse(b"RF"), Err(ParseError::TooShort(2)));
+    let mut bad = b"XF9\x01\x00\x10KWK1CGQ11AHZL0CGQ11A\x03\x00\x00".to_vec();
+    assert_eq!(Frame::parse(&bad), Err(ParseError::BadMagic(*b"XF")));
+    bad[0] = b'R';
+    bad[8] = b'k';
+    assert_eq!(
+        Frame::parse(&bad),
+        Err(ParseError::BadSerial(b"KWk1CGQ11A".to_vec()))
+    );
+}
+
+#[test]
+#[cfg(debug_assertions)]
+#[should_panic(expected = "serial")]
+fn test_serial_length() {
+    let mut frame =
+        Frame::parse(b"RF9\x01\x00\x10KWK1CGQ11AHZL0CGQ11A\x03\x00\x00").expect("valid frame");
+    frame.link_serial.push('X');
+    frame.to_bytes();
+}
@@ -0,0 +1,261 @@
+//! damage IQ the way the real link seems to be damaged, for measuring the decoder
+//!
+//! The waterfalls (docs/fm-1.jpg, docs/disc-2.jpg) show "vertical glitches", where the carrier
+//! jumps mid-packet. We don't know what causes them, so we have a few guesses at what they are.
+
+use crate::modulate::Rng;
+use num_complex::Complex32;
+use std::f64::consts::TAU;
+use std::ops::Range;
+
+pub struct Config {
+    pub sample_rate: u32,
+    pub bit_rate: u32,
+    /// where the damage can go, e.g. the transmission, excluding any padding
+    pub within: Range<usize>,
+    /// sudden changes of carrier phase, of any amount
+    pub phase_jumps: usize,
+    /// places where some samples are lost, so the bits after them are early
+    pub dropouts: usize,
+    /// the most that's lost at each dropout, as a fraction of a bit
+    pub max_dropout: f64,
+    /// short periods where the carrier is somewhere else
+    pub excursions: usize,
+    /// how far the carrier goes (Hz), at most, in either direction
+    pub max_excursion: f64,
+    /// how long it goes for, at most, in bits
+    pub max_excursion_bits: f64,
+    pub seed: u64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Impairment {
+    PhaseJump { at: usize, radians: f64 },
+    Dropout { at: usize, len: usize },
+    Excursion { at: usize, len: usize, hz: f64 },
+}
+
+/// returns the damaged samples, and what was done to them, in terms of the original positions
+pub fn impair(samples: &[Complex32], config: &Config) -> (Vec<Complex32>, Vec<Impairment>) {
+    assert!(config.within.end <= samples.len(), "impairments must fit");
+    let samples_per_bit = f64::from(config.sample_rate) / f64::from(config.bit_rate);
+
+    let mut rng = Rng::new(config.seed);
+    let at = |rng: &mut Rng| {
+        let width = config.within.len() as f64;
+        config.within.start + (rng.uniform() * width) as usize
+    };
+
+    let mut done = Vec::with_capacity(config.phase_jumps + config.dropouts + config.excursions);
+    for _ in 0..config.phase_jumps {
+        done.push(Impairment::PhaseJump {
+            at: at(&mut rng),
+            radians: rng.uniform() * TAU,
+        });
+    }
+    for _ in 0..config.dropouts {
+        done.push(Impairment::Dropout {
+            at: at(&mut rng),
+            len: (rng.uniform() * config.max_dropout * samples_per_bit).round() as usize,
+        });
+    }
+    for _ in 0..config.excursions {
+        done.push(Impairment::Excursion {
+            at: at(&mut rng),
+            len: (rng.uniform() * config.max_excursion_bits * samples_per_bit).round() as usize,
+            hz: (rng.uniform() * 2. - 1.) * config.max_excursion,
+        });
+    }
+    done.sort_by_key(|v| match v {
+        Impairment::PhaseJump { at, .. }
+        | Impairment::Dropout { at, .. }
+        | Impairment::Excursion { at, .. } => *at,
+    });
+
+    // the phase changes, in order, then the removals, from the end, so positions stay valid
+    let mut out = samples.to_vec();
+    let step = TAU / f64::from(config.sample_rate);
+    for impairment in &done {
+        match *impairment {
+            Impairment::PhaseJump { at, radians } => rotate(&mut out[at..], radians),
+            Impairment::Excursion { at, len, hz } => {
+                let end = (at + len).min(out.len());
+                for (i, v) in out[at..end].iter_mut().enumerate() {
+                    *v *= Complex32::from_polar(1., (step * hz * i as f64) as f32);
+                }
+                // the carrier comes back, but wherever its phase got to
+                rotate(&mut out[end..], step * hz * (end - at) as f64);
+            }
+            Impairment::Dropout { .. } => (),
+        }
+    }
+    for impairment in done.iter().rev() {
+        if let Impairment::Dropout { at, len } = *impairment {
+            out.drain(at..(at + len).min(out.len()));
+        }
+    }
+
+    (out, done)
+}
+
+fn rotate(samples: &mut [Complex32], radians: f64) {
+    let by = Complex32::from_polar(1., radians as f32);
+    for v in samples {
+        *v *= by;
+    }
+}
+
+#[test]
+fn test_impair() {
+    let samples = vec![Complex32::new(1., 0.); 1000];
+    let config = Config {
+        sample_rate: 1_000_000,
+        bit_rate: 10_000,
+        within: 100..900,
+        phase_jumps: 2,
+        dropouts: 3,
+        max_dropout: 0.5,
+        excursions: 1,
+        max_excursion: 1000.,
+        max_excursion_bits: 1.,
+        seed: 4,
+    };
+
+    let (out, done) = impair(&samples, &config);
+    assert_eq!(done.len(), 6);
+    let dropped = done
+        .iter()
+        .map(|v| match v {
+            Impairment::Dropout { len, .. } => *len,
+            _ => 0,
+        })
+        .sum::<usize>();
+    assert_eq!(out.len(), samples.len() - dropped);
+
+    // nothing before the first impairment is touched, and the power is unchanged
+    assert_eq!(out[..100], samples[..100]);
+    assert!(out.iter().all(|v| (v.norm() - 1.).abs() < 1e-5));
+
+    // seeded
+    assert_eq!(impair(&samples, &config).1, done);
+}
+
+/// how many of `trials` decode, with this damage to each
+#[cfg(test)]
+fn yield_with(
+    trials: u64,
+    decoder: &crate::decode::Config,
+    damage: impl Fn(u64) -> Config,
+) -> usize {
+    use crate::decode::{squelch_decode, synthesise};
+    use crate::encode::encode;
+    use crate::frame::Frame;
+    use crate::modulate;
+
+    let frame = Frame::parse(b"RF9\x01\x00\x10KWK1CGQ11AHZL0CGQ11A\x03\x00\x04\x00\x01\x02\x03")
+        .expect("valid frame");
+    let modulation = modulate::Config::default();
+    let clean = synthesise(&encode(&frame), &modulation);
+    let transmission = modulation.padding..clean.len() - modulation.padding;
+
+    (0..trials)
+        .filter(|&seed| {
+            let (damaged, _) = impair(
+                &clean,
+                &Config {
+                    within: transmission.clone(),
+                    ..damage(seed)
+                },
+            );
+            squelch_decode(&damaged, &modulation, decoder)
+                .iter()
+                .flat_map(|v| v.matches_crc.keys())
+                .any(|v| Frame::parse(v).as_ref() == Ok(&frame))
+        })
+        .count()
+}
+
+/// how many of `trials` transmissions with each kind of damage (phase jumps, dropouts,
+/// excursions) decode, for each decoder, which must be at least its floors
+#[cfg(test)]
+fn check_yields(trials: u64, decoders: &[(crate::decode::Clock, usize, [usize; 3])]) {
+    use crate::decode;
+
+    let none = |seed| Config {
+        sample_rate: 2_880_000,
+        bit_rate: 10_000,
+        within: 0..0,
+        phase_jumps: 0,
+        dropouts: 0,
+        max_dropout: 0.,
+        excursions: 0,
+        max_excursion: 0.,
+        max_excursion_bits: 0.,
+        seed,
+    };
+
+    for &(clock, repair_budget, floors) in decoders {
+        let decoder = decode::Config {
+            clock,
+            repair_budget,
+            ..decode::Config::default()
+        };
+        let phase_jumps = yield_with(trials, &decoder, |seed| Config {
+            phase_jumps: 8,
+            ..none(seed)
+        });
+        let dropouts = yield_with(trials, &decoder, |seed| Config {
+            dropouts: 4,
+            max_dropout: 0.8,
+            ..none(seed)
+        });
+        let excursions = yield_with(trials, &decoder, |seed| Config {
+            excursions: 4,
+            max_excursion: 200_000.,
+            max_excursion_bits: 1.,
+            ..none(seed)
+        });
+
+        let got = [phase_jumps, dropouts, excursions];
+        assert!(
+            got.iter().zip(floors).all(|(&got, floor)| got >= floor),
+            "{clock:?}, repairing {repair_budget}: {got:?} of {trials}, below {floors:?}"
+        );
+    }
+}
+
+/// a floor for the decoder's tolerance of each kind of damage; raise these as it improves
+///
+/// Slow, so only run when working on the decoder, with `--ignored`.
+#[test]
+#[ignore]
+fn test_decoder_yield() {
+    use crate::decode::Clock;
+
+    check_yields(
+        16,
+        &[
+            (Clock::Sweep, 0, [16, 10, 5]),
+            (Clock::Gardner, 0, [16, 3, 8]),
+            (Clock::Sweep, 2, [16, 11, 9]),
+            (Clock::Gardner, 2, [16, 3, 9]),
+            (Clock::Correlate, 0, [16, 6, 11]),
+        ],
+    );
+}
+
+/// a few trials of `test_decoder_yield`, so a decoder which breaks outright is noticed; enough
+/// that every decoder gets some of each kind of damage through
+#[test]
+fn test_decoder_yield_smoke() {
+    use crate::decode::Clock;
+
+    check_yields(
+        8,
+        &[
+            (Clock::Sweep, 0, [8, 5, 1]),
+            (Clock::Gardner, 0, [8, 1, 3]),
+            (Clock::Correlate, 0, [8, 1, 6]),
+        ],
+    );
+}
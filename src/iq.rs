@@ -0,0 +1,316 @@
+//! reading interleaved IQ recordings, in the formats the various tools like to produce
+
+use crate::source::SampleSource;
+use crate::u8_to_f32;
+use anyhow::{Context, Result, anyhow, bail, ensure};
+use num_complex::Complex32;
+use std::io::Read;
+use std::path::Path;
+use std::str::FromStr;
+use std::{fs, io};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    /// unsigned 8-bit, as rtl-sdr produces
+    Cu8,
+    /// signed 8-bit, as hackrf produces
+    Cs8,
+    /// signed 16-bit little endian
+    Cs16,
+    /// 32-bit float little endian, as gnuradio (and pulseview, `.fc32`) produce
+    Cf32,
+    /// a two-channel wav file, in any of the above sample formats
+    Wav,
+}
+
+impl Format {
+    /// from the extension, e.g. `inbound.sr3200000.fc32`
+    pub fn from_path(path: impl AsRef<Path>) -> Option<Format> {
+        path.as_ref().extension()?.to_str()?.parse().ok()
+    }
+
+    fn bytes_per_sample(self) -> usize {
+        match self {
+            Format::Cu8 | Format::Cs8 => 2,
+            Format::Cs16 => 4,
+            Format::Cf32 => 8,
+            Format::Wav => unreachable!("wav is resolved to its sample format on open"),
+        }
+    }
+
+    fn convert(self, iq: &[u8]) -> Complex32 {
+        match self {
+            Format::Cu8 => Complex32::new(u8_to_f32(iq[0]), u8_to_f32(iq[1])),
+            Format::Cs8 => Complex32::new(iq[0] as i8 as f32 / 128., iq[1] as i8 as f32 / 128.),
+            Format::Cs16 => Complex32::new(
+                i16::from_le_bytes([iq[0], iq[1]]) as f32 / 32768.,
+                i16::from_le_bytes([iq[2], iq[3]]) as f32 / 32768.,
+            ),
+            Format::Cf32 => Complex32::new(
+                f32::from_le_bytes([iq[0], iq[1], iq[2], iq[3]]),
+                f32::from_le_bytes([iq[4], iq[5], iq[6], iq[7]]),
+            ),
+            Format::Wav => unreachable!("wav is resolved to its sample format on open"),
+        }
+    }
+}
+
+impl FromStr for Format {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Format> {
+        Ok(match s.to_ascii_lowercase().as_str() {
+            "cu8" => Format::Cu8,
+            "cs8" => Format::Cs8,
+            "cs16" => Format::Cs16,
+            "cf32" | "fc32" => Format::Cf32,
+            "wav" => Format::Wav,
+            other => bail!("unknown iq format: {other:?}"),
+        })
+    }
+}
+
+pub struct IqReader<R> {
+    inner: R,
+    format: Format,
+    /// only known for wav files
+    sample_rate: Option<u32>,
+    /// bytes left in a wav's data chunk; anything after it is another chunk, not samples
+    remaining: Option<u64>,
+    bytes: Vec<u8>,
+    /// bytes of a partial sample, left at the start of `bytes` by the last read
+    carried: usize,
+}
+
+impl<R: Read> IqReader<R> {
+    /// for wav, this reads the header
+    pub fn new(mut inner: R, format: Format) -> Result<IqReader<R>> {
+        let (format, sample_rate, remaining) = match format {
+            Format::Wav => {
+                let (format, sample_rate, len) = read_wav_header(&mut inner)?;
+                (format, Some(sample_rate), Some(len))
+            }
+            format => (format, None, None),
+        };
+
+        Ok(IqReader {
+            inner,
+            format,
+            sample_rate,
+            remaining,
+            bytes: Vec::new(),
+            carried: 0,
+        })
+    }
+
+    /// the sample format, after looking inside any container
+    pub fn format(&self) -> Format {
+        self.format
+    }
+
+    /// if the file says
+    pub fn sample_rate(&self) -> Option<u32> {
+        self.sample_rate
+    }
+}
+
+/// open a recording, guessing the format from the extension if it's not provided
+pub fn open(
+    path: impl AsRef<Path>,
+    format: Option<Format>,
+) -> Result<IqReader<io::BufReader<fs::File>>> {
+    let path = path.as_ref();
+    let format = match format {
+        Some(format) => format,
+        None => Format::from_path(path)
+            .with_context(|| format!("can't guess the iq format of {}", path.display()))?,
+    };
+    IqReader::new(io::BufReader::new(fs::File::open(path)?), format)
+        .with_context(|| format!("opening {}", path.display()))
+}
+
+impl<R: Read> SampleSource for IqReader<R> {
+    fn read_samples(&mut self, buf: &mut [Complex32]) -> Result<usize> {
+        let bps = self.format.bytes_per_sample();
+        self.bytes.resize(buf.len() * bps, 0);
+
+        // keep reading until there's at least one whole sample, or the stream ends;
+        // a trailing partial sample at the end of the stream is dropped
+        let mut filled = self.carried;
+        let end = self.remaining.map_or(self.bytes.len(), |remaining| {
+            let remaining = usize::try_from(remaining).unwrap_or(usize::MAX);
+            self.bytes.len().min(filled.saturating_add(remaining))
+        });
+        while filled < bps {
+            match self.inner.read(&mut self.bytes[filled..end]) {
+                Ok(0) => break,
+                Ok(len) => {
+                    filled += len;
+                    if let Some(remaining) = &mut self.remaining {
+                        *remaining -= len as u64;
+                    }
+                }
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e.into()),
+            }
+        }
+
+        let samples = filled / bps;
+        for (out, iq) in buf.iter_mut().zip(self.bytes[..filled].chunks_exact(bps)) {
+            *out = self.format.convert(iq);
+        }
+
+        self.carried = filled % bps;
+        self.bytes
+            .copy_within(samples * bps..samples * bps + self.carried, 0);
+        Ok(samples)
+    }
+}
+
+/// returns the sample format, the sample rate, and the length of the samples (bytes), leaving
+/// `inner` at the start of them
+fn read_wav_header(inner: &mut impl Read) -> Result<(Format, u32, u64)> {
+    let mut riff = [0u8; 12];
+    inner.read_exact(&mut riff)?;
+    ensure!(
+        &riff[..4] == b"RIFF" && &riff[8..] == b"WAVE",
+        "not a wav file"
+    );
+
+    let mut format = None;
+    loop {
+        let mut header = [0u8; 8];
+        inner.read_exact(&mut header).context("no data chunk")?;
+        let len = u32::from_le_bytes(header[4..].try_into().expect("fixed size")) as usize;
+
+        match &header[..4] {
+            b"fmt " => {
+                let mut fmt = vec![0u8; len];
+                inner.read_exact(&mut fmt)?;
+                ensure!(len >= 16, "fmt chunk too short");
+                let le16 = |i: usize| u16::from_le_bytes([fmt[i], fmt[i + 1]]);
+
+                let mut tag = le16(0);
+                let channels = le16(2);
+                let sample_rate = u32::from_le_bytes(fmt[4..8].try_into().expect("fixed size"));
+                let bits = le16(14);
+
+                // WAVE_FORMAT_EXTENSIBLE hides the real tag at the start of the sub-format guid
+                if tag == 0xfffe && len >= 26 {
+                    tag = le16(24);
+                }
+
+                ensure!(
+                    channels == 2,
+                    "expected two channels (I and Q), not {channels}"
+                );
+                let sample_format = match (tag, bits) {
+                    (1, 8) => Format::Cu8,
+                    (1, 16) => Format::Cs16,
+                    (3, 32) => Format::Cf32,
+                    _ => bail!("unsupported wav sample format: tag {tag}, {bits} bits"),
+                };
+                format = Some((sample_format, sample_rate));
+            }
+            b"data" => {
+                let (format, sample_rate) =
+                    format.ok_or_else(|| anyhow!("data chunk before fmt chunk"))?;
+                return Ok((format, sample_rate, len as u64));
+            }
+            _ => {
+                io::copy(&mut inner.by_ref().take(len as u64), &mut io::sink())?;
+            }
+        }
+        // chunks are padded to even lengths
+        if len % 2 == 1 {
+            inner.read_exact(&mut [0u8; 1])?;
+        }
+    }
+}
+
+#[cfg(test)]
+fn read_all(mut source: impl SampleSource) -> Vec<Complex32> {
+    let mut buf = [Complex32::new(9., 9.); 3];
+    let mut seen = Vec::new();
+    loop {
+        let len = source.read_samples(&mut buf).expect("in-memory");
+        if len == 0 {
+            return seen;
+        }
+        seen.extend_from_slice(&buf[..len]);
+    }
+}
+
+#[test]
+fn test_formats() {
+    let expected = [Complex32::new(0., -1.), Complex32::new(-0.5, 0.5)];
+    let read = |bytes: &[u8], format| read_all(IqReader::new(bytes, format).expect("valid header"));
+
+    assert_eq!(read(&[128, 0, 64, 192], Format::Cu8), expected);
+    assert_eq!(read(&[0, 0x80, 0xc0, 0x40], Format::Cs8), expected);
+    assert_eq!(
+        read(&[0, 0, 0, 0x80, 0, 0xc0, 0, 0x40], Format::Cs16),
+        expected
+    );
+
+    let cf32 = [0f32, -1., -0.5, 0.5]
+        .iter()
+        .flat_map(|v| v.to_le_bytes())
+        .collect::<Vec<u8>>();
+    assert_eq!(read(&cf32, Format::Cf32), expected);
+
+    // trailing partial samples are dropped
+    assert_eq!(read(&cf32[..cf32.len() - 1], Format::Cf32), expected[..1]);
+}
+
+#[test]
+fn test_partial_reads() {
+    // a reader which only ever returns three bytes at a time, to split samples
+    struct Dribble<'a>(&'a [u8]);
+    impl Read for Dribble<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let len = buf.len().min(self.0.len()).min(3);
+            buf[..len].copy_from_slice(&self.0[..len]);
+            self.0 = &self.0[len..];
+            Ok(len)
+        }
+    }
+
+    let source = IqReader::new(Dribble(&[0, 0, 0, 0x80, 0, 0xc0, 0, 0x40, 7]), Format::Cs16)
+        .expect("no header");
+    assert_eq!(
+        read_all(source),
+        [Complex32::new(0., -1.), Complex32::new(-0.5, 0.5)]
+    );
+}
+
+#[test]
+fn test_wav() {
+    let mut wav = b"RIFF\0\0\0\0WAVE".to_vec();
+    wav.extend_from_slice(b"LIST\x03\0\0\0abc\0");
+    wav.extend_from_slice(b"fmt \x10\0\0\0");
+    // pcm, two channels, 48k, 192k bytes/s, 4 byte frames, 16 bits
+    wav.extend_from_slice(&[1, 0, 2, 0, 0x80, 0xbb, 0, 0, 0, 0xee, 2, 0, 4, 0, 16, 0]);
+    wav.extend_from_slice(b"data\x08\0\0\0");
+    wav.extend_from_slice(&[0, 0, 0, 0x80, 0, 0xc0, 0, 0x40]);
+    // another chunk after the samples, which isn't more samples
+    wav.extend_from_slice(b"LIST\x04\0\0\0abcd");
+
+    let source = IqReader::new(wav.as_slice(), Format::Wav).expect("valid header");
+    assert_eq!(source.format(), Format::Cs16);
+    assert_eq!(source.sample_rate(), Some(48_000));
+    assert_eq!(
+        read_all(source),
+        [Complex32::new(0., -1.), Complex32::new(-0.5, 0.5)]
+    );
+}
+
+#[test]
+fn test_from_path() {
+    assert_eq!(
+        Format::from_path("one-chat/inbound.sr3200000.fc32"),
+        Some(Format::Cf32)
+    );
+    assert_eq!(Format::from_path("foo.CU8"), Some(Format::Cu8));
+    assert_eq!(Format::from_path("foo.squelch.sr180000.f32"), None);
+}
@@ -0,0 +1,288 @@
+//! decoding with what we already know: the serials of the devices on our site
+//!
+//! Every frame starts with "RF", the sequence number, the version bytes, both serials and the
+//! unknown byte: 26 of its first 27 bytes are predictable, once we've seen one packet. That's a
+//! much longer sync word than "jack", which survives damage to "jack" itself, and tells us
+//! where in the key we are, and whether the bits slipped part way through the header.
+
+use crate::bits_to_byte;
+use crate::decode::{Candidate, Candidates, ENCRYPTION_KEY};
+use crate::frame::{Frame, parse_serial};
+use anyhow::{Context, Result, bail};
+use itertools::Itertools;
+use memchr::memmem;
+use std::collections::HashSet;
+use std::str::FromStr;
+
+/// of the 208 predictable bits, how many can be wrong before we don't believe it's a match
+const MAX_ERRORS: usize = 16;
+/// how far (in bits) the stream may have slipped, in either direction, in the header
+const MAX_SLIP: isize = 2;
+/// the frame is found by any of these (four byte) pieces of it, which must be exactly right
+const ANCHORS: [usize; 6] = [3, 7, 11, 15, 19, 23];
+/// the end of the predictable part of the frame
+const KNOWN_LEN: usize = 27;
+/// the sequence number, the only unpredictable byte in the header
+const SEQ_AT: usize = 2;
+
+/// a ShineLanBox and ShineLink pair
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Known {
+    pub lanbox_serial: String,
+    pub link_serial: String,
+}
+
+impl FromStr for Known {
+    type Err = anyhow::Error;
+
+    /// `KWK1CGQ11A/HZL0CGQ11A`, or the two run together, as `listen` prints them
+    fn from_str(s: &str) -> Result<Known> {
+        let (lanbox, link) = match s.split_once('/') {
+            Some(pair) => pair,
+            None => (s.get(..10).unwrap_or(s), s.get(10..).unwrap_or("")),
+        };
+        if lanbox.len() != 10 || link.len() != 10 {
+            bail!("expected two ten character serials, like KWK1CGQ11A/HZL0CGQ11A, not {s:?}");
+        }
+        for serial in [lanbox, link] {
+            parse_serial(serial.as_bytes()).with_context(|| format!("in {s:?}"))?;
+        }
+        Ok(Known {
+            lanbox_serial: lanbox.to_string(),
+            link_serial: link.to_string(),
+        })
+    }
+}
+
+impl Known {
+    /// whether the frame is between these two
+    pub fn matches(&self, frame: &Frame) -> bool {
+        frame.lanbox_serial == self.lanbox_serial && frame.link_serial == self.link_serial
+    }
+
+    /// the start of any frame between these two, with a zero sequence number
+    fn plain(&self) -> Vec<u8> {
+        let mut plain = Frame {
+            seq: 0,
+            version: [1, 0, 0x10],
+            lanbox_serial: self.lanbox_serial.clone(),
+            link_serial: self.link_serial.clone(),
+            unknown: 3,
+            packet_type: 0,
+            payload: Vec::new(),
+        }
+        .to_plain_bytes();
+        plain.truncate(KNOWN_LEN);
+        plain
+    }
+}
+
+/// Find frames from any of the `known` pairs in the bits, at any key phase, and add them to the
+/// candidates as if they were found after "jack", re-encrypted as if they were aligned, with the
+/// header replaced by what it must have been, and any slip in the header spliced out.
+pub(crate) fn after_template(
+    bits: &[bool],
+    confidence: &[f32],
+    bit_period: f32,
+    known: &[Known],
+    candidate_bytes: &mut Candidates,
+) {
+    // the same at every phase, for every pair
+    let packed = (0..8)
+        .map_while(|offset| bits.get(offset..))
+        .map(|bits| bits.chunks_exact(8).map(bits_to_byte).collect_vec())
+        .collect_vec();
+    let mut tried = HashSet::new();
+    for (n, pair) in known.iter().enumerate() {
+        let plain = pair.plain();
+        for phase in 0..ENCRYPTION_KEY.len() {
+            let template = plain
+                .iter()
+                .enumerate()
+                .map(|(i, &p)| p ^ key(i + phase))
+                .collect_vec();
+
+            for (offset, bytes) in packed.iter().enumerate() {
+                for anchor in ANCHORS {
+                    let needle = &template[anchor..anchor + 4];
+                    for found in memmem::find_iter(bytes, needle) {
+                        // where the length byte is
+                        let Some(start) = found.checked_sub(anchor + 1) else {
+                            continue;
+                        };
+                        let start = offset + start * 8;
+                        if !tried.insert((start, phase, n)) {
+                            continue;
+                        }
+                        if let Some((bits, confidence)) = splice(bits, confidence, start, &template)
+                        {
+                            let cand = realign(&bits, &plain, phase);
+                            candidate_bytes.entry(cand).or_insert(Candidate {
+                                confidence,
+                                bit_period,
+                            });
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+fn key(i: usize) -> u8 {
+    ENCRYPTION_KEY[i % ENCRYPTION_KEY.len()]
+}
+
+/// The bits from the length byte at `start`, if the header matches `template`, possibly after
+/// the stream slipped by a bit or two part way through it, in which case the bits after the
+/// slip are moved back to where they should be.
+fn splice(
+    bits: &[bool],
+    confidence: &[f32],
+    start: usize,
+    template: &[u8],
+) -> Option<(Vec<bool>, Vec<f32>)> {
+    // how many bits of the template are wrong in frame bytes `range`, if it starts at `at`
+    let errors = |range: std::ops::Range<usize>, at: isize| {
+        range
+            .filter(|&i| i != SEQ_AT)
+            .flat_map(|i| (0..8).map(move |b| (i, b)))
+            .filter(|&(i, b)| {
+                let expected = template[i] >> (7 - b) & 1 == 1;
+                let pos = at + ((1 + i) * 8 + b) as isize;
+                usize::try_from(pos)
+                    .ok()
+                    .and_then(|pos| bits.get(pos))
+                    .is_none_or(|&bit| bit != expected)
+            })
+            .count()
+    };
+
+    let at = start as isize;
+    // (errors, slip, first byte after the slip)
+    let (wrong, slip, from) = if errors(0..KNOWN_LEN, at) <= MAX_ERRORS {
+        (0, 0, KNOWN_LEN)
+    } else {
+        (-MAX_SLIP..=MAX_SLIP)
+            .filter(|&slip| slip != 0)
+            .cartesian_product(1..KNOWN_LEN)
+            .map(|(slip, from)| {
+                (
+                    errors(0..from, at) + errors(from..KNOWN_LEN, at + slip),
+                    slip,
+                    from,
+                )
+            })
+            .min()?
+    };
+    if wrong > MAX_ERRORS {
+        return None;
+    }
+
+    let split = start + (1 + from) * 8;
+    let resume = usize::try_from(split as isize + slip).ok()?;
+    let bits = [bits.get(start..split)?, bits.get(resume..)?].concat();
+    let confidence = [confidence.get(start..split)?, confidence.get(resume..)?].concat();
+    Some((bits, confidence))
+}
+
+/// the bytes, with the header replaced by `plain`, and re-encrypted as if the key started on
+/// the byte after the length
+fn realign(bits: &[bool], plain: &[u8], phase: usize) -> Vec<u8> {
+    let mut bytes = bits.chunks_exact(8).map(bits_to_byte).collect_vec();
+    for (i, byte) in bytes.iter_mut().enumerate().skip(1) {
+        let frame_at = i - 1;
+        *byte = match plain.get(frame_at) {
+            Some(&p) if frame_at != SEQ_AT => p ^ key(frame_at),
+            _ => *byte ^ key(frame_at + phase) ^ key(frame_at),
+        };
+    }
+    bytes
+}
+
+#[cfg(test)]
+fn test_bits(damage: impl Fn(&mut Vec<bool>)) -> Option<Vec<u8>> {
+    use crate::decode::decrypt_aligned;
+    use crate::encode::encode;
+
+    let frame = Frame::parse(b"RF9\x01\x00\x10KWK1CGQ11AHZL0CGQ11A\x03\x00\x04\x00\x01\x02\x03")
+        .expect("valid frame");
+    let mut bits = encode(&frame)
+        .iter()
+        .flat_map(|byte| (0..8).rev().map(move |i| byte >> i & 1 == 1))
+        .collect_vec();
+    damage(&mut bits);
+    let confidence = vec![1.; bits.len()];
+
+    let known = "KWK1CGQ11AHZL0CGQ11A".parse::<Known>().expect("valid");
+    let mut candidates = Candidates::new();
+    after_template(&bits, &confidence, 18., &[known], &mut candidates);
+    let frames = candidates
+        .keys()
+        .filter_map(|v| decrypt_aligned(v))
+        .collect_vec();
+    assert!(
+        frames.iter().all(|v| *v == frame.to_plain_bytes()),
+        "{frames:?}"
+    );
+    frames.into_iter().next()
+}
+
+#[test]
+fn test_known_without_sync() {
+    // "jack" is at bits 40..72; lose most of it, and damage a serial
+    assert!(
+        test_bits(|bits| {
+            for i in (44..72).chain([130, 140, 150]) {
+                bits[i] = !bits[i];
+            }
+        })
+        .is_some()
+    );
+
+    // encrypted with the key three bytes further on
+    assert!(
+        test_bits(|bits| {
+            let frame_bits = &mut bits[80..];
+            for (i, byte) in frame_bits.chunks_exact_mut(8).enumerate() {
+                let flip = key(i) ^ key(i + 3);
+                for (b, bit) in byte.iter_mut().enumerate() {
+                    *bit ^= flip >> (7 - b) & 1 == 1;
+                }
+            }
+        })
+        .is_some()
+    );
+
+    // not serials
+    assert!("kwk1cgq11a/HZL0CGQ11A".parse::<Known>().is_err());
+    assert!("KWK1CGQ11A/HZL0CGQ1".parse::<Known>().is_err());
+    assert!("KWK1CGQ11A/HZL0-GQ11".parse::<Known>().is_err());
+    assert!("KWK1CGQ11AXYZ/HZL0CGQ11A".parse::<Known>().is_err());
+    assert!("KWK1CGQ11AHZL0CGQ11AXYZ".parse::<Known>().is_err());
+
+    // a different site
+    assert_eq!(
+        "AAAAAAAAAA/BBBBBBBBBB"
+            .parse::<Known>()
+            .expect("valid")
+            .plain()[6..10],
+        *b"AAAA"
+    );
+}
+
+#[test]
+fn test_known_slip() {
+    // a bit lost in the ShineLink serial (which starts at bit 80 + 16 * 8)
+    assert!(
+        test_bits(|bits| {
+            bits.remove(80 + 19 * 8 + 3);
+        })
+        .is_some()
+    );
+    // and one gained, in the ShineLanBox serial
+    assert!(test_bits(|bits| bits.insert(80 + 12 * 8, true)).is_some());
+
+    // but not arbitrary damage to the payload
+    assert!(test_bits(|bits| bits[80 + 29 * 8] = !bits[80 + 29 * 8]).is_none());
+}
@@ -1,8 +1,26 @@
 pub mod bulk;
+pub mod clock;
+pub mod combine;
 pub mod crc;
 pub mod decode;
 pub mod demod_fm;
+pub mod dsp;
+pub mod encode;
+pub mod frame;
+pub mod impair;
+pub mod iq;
+pub mod known;
+pub mod listen;
+pub mod modulate;
+pub mod offset;
+pub mod packets;
+mod repair;
+pub mod session;
+pub mod sigmf;
+mod slip;
+pub mod source;
 pub mod squelch;
+pub mod sync;
 
 use anyhow::Result;
 use num_complex::Complex;
@@ -28,7 +46,7 @@ pub fn read_to_end_f32(input: impl AsRef<Path>) -> Result<Vec<f32>> {
         .collect())
 }
 
-fn u8_to_f32(v: u8) -> f32 {
+pub(crate) fn u8_to_f32(v: u8) -> f32 {
     (v as f32 - 128.0) / 128.0
 }
 
@@ -0,0 +1,110 @@
+//! decode frames from a never-ending source, e.g. a radio
+
+use crate::decode::decode;
+use crate::frame::Frame;
+use crate::source::SampleSource;
+use crate::squelch::{Config, Squelch};
+use anyhow::Result;
+use num_complex::Complex32;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// read from `source` until it ends, or `stop` is set, calling `on_frame` for each frame
+/// decoded, with the chunk (of `CHUNK_BY` observations) after it ended
+pub fn listen(
+    source: &mut impl SampleSource,
+    config: &Config,
+    stop: &AtomicBool,
+    mut on_frame: impl FnMut(usize, Frame),
+) -> Result<()> {
+    // 100us transitions
+    let edge_length = config.sample_rate as f32 / config.decimation as f32 / 10_000.;
+
+    let mut squelch = Squelch::new(config)?;
+    let mut decode_frames = |end: usize, fm: Vec<f32>| {
+        let (crc, _) = decode(&fm, edge_length);
+        for frame in crc.iter().filter_map(|v| Frame::parse(v).ok()) {
+            on_frame(end, frame);
+        }
+    };
+
+    let mut buf = vec![Complex32::new(0., 0.); 64 * 1024];
+    while !stop.load(Ordering::Relaxed) {
+        let len = source.read_samples(&mut buf)?;
+        if len == 0 {
+            break;
+        }
+        for signal in squelch.push(&buf[..len]) {
+            decode_frames(signal.end, signal.fm);
+        }
+    }
+
+    if let Some(signal) = squelch.finish() {
+        decode_frames(signal.end, signal.fm);
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+pub(crate) fn one_chat_outbound() -> Vec<Complex32> {
+    let mut source = crate::iq::open(
+        concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/one-chat/outbound.sr3200000.fc32"
+        ),
+        None,
+    )
+    .expect("one-chat capture");
+
+    // a read may stop short of the end
+    let mut samples = Vec::new();
+    let mut block = vec![Complex32::new(0., 0.); 65_536];
+    loop {
+        let len = source.read_samples(&mut block).expect("readable");
+        if len == 0 {
+            break samples;
+        }
+        samples.extend_from_slice(&block[..len]);
+    }
+}
+
+#[cfg(test)]
+pub(crate) fn quiet_noise(len: usize, seed: u32) -> Vec<Complex32> {
+    let mut state = seed;
+    let mut next = move || {
+        state = state.wrapping_mul(1_664_525).wrapping_add(1_013_904_223);
+        (state >> 8) as f32 / (1 << 24) as f32 - 0.5
+    };
+    (0..len)
+        .map(|_| Complex32::new(next(), next()) * 0.01)
+        .collect()
+}
+
+#[test]
+fn test_listen_one_chat() {
+    let config = Config {
+        decimation: 16,
+        sample_rate: 3_200_000,
+        deviation: 60_000,
+        shift: 257_000.,
+        filter: None,
+        energy: None,
+    };
+
+    let mut samples = quiet_noise(640_000, 1);
+    samples.extend(one_chat_outbound());
+    samples.extend(quiet_noise(640_000, 2));
+
+    let mut seen = Vec::new();
+    listen(
+        &mut samples.as_slice(),
+        &config,
+        &AtomicBool::new(false),
+        |offset, frame| seen.push((offset, frame)),
+    )
+    .expect("in-memory");
+
+    assert_eq!(seen.len(), 1, "{seen:?}");
+    assert_eq!(seen[0].1.lanbox_serial, "KWK1CGQ11A");
+    assert_eq!(seen[0].1.payload, b"");
+}
@@ -0,0 +1,225 @@
+//! generate the IQ a ShineLink would, for known frames, so the decoder can be checked against them
+
+use num_complex::Complex32;
+use std::f64::consts::{LN_2, TAU};
+
+pub struct Config {
+    pub sample_rate: u32,
+    /// the decoder assumes 100us bits
+    pub bit_rate: u32,
+    /// of a one (up) or zero (down) from the carrier (Hz)
+    pub deviation: u32,
+    /// where the carrier is, relative to the middle of the capture (Hz);
+    /// `squelch::Config::shift` is the negation of this
+    pub offset: f64,
+    /// bandwidth-time product of the gaussian filter, e.g. 0.5, or plain FSK if `None`
+    pub bt: Option<f64>,
+    /// of the carrier; noise is relative to this
+    pub amplitude: f32,
+    /// samples of nothing (but noise) either side of the transmission
+    pub padding: usize,
+    /// standard deviation of the noise added to each of I and Q
+    pub noise: f32,
+    /// how fast the carrier moves (Hz/s), from `offset` at the start of the transmission
+    pub drift: f64,
+    /// sudden carrier phase changes: (sample, radians)
+    pub phase_jumps: Vec<(usize, f64)>,
+    /// for the noise
+    pub seed: u64,
+}
+
+impl Default for Config {
+    fn default() -> Config {
+        Config {
+            sample_rate: 2_880_000,
+            bit_rate: 10_000,
+            deviation: 30_000,
+            offset: 0.,
+            bt: None,
+            amplitude: 0.5,
+            padding: 100_000,
+            noise: 0.01,
+            drift: 0.,
+            phase_jumps: Vec::new(),
+            seed: 0,
+        }
+    }
+}
+
+/// the bytes (e.g. from `encode`), most significant bit first, as IQ
+pub fn modulate(on_air: &[u8], config: &Config) -> Vec<Complex32> {
+    let samples_per_bit = f64::from(config.sample_rate) / f64::from(config.bit_rate);
+    let symbols = on_air
+        .iter()
+        .flat_map(|byte| (0..8).rev().map(move |i| byte >> i & 1 == 1))
+        .map(|bit| if bit { 1. } else { -1. })
+        .collect::<Vec<f64>>();
+
+    let len = (symbols.len() as f64 * samples_per_bit).round() as usize;
+    let mut frequency = (0..len)
+        .map(|i| symbols[((i as f64 / samples_per_bit) as usize).min(symbols.len() - 1)])
+        .collect::<Vec<f64>>();
+    if let Some(bt) = config.bt {
+        frequency = gaussian_filter(&frequency, bt, samples_per_bit);
+    }
+
+    let mut rng = Rng::new(config.seed);
+    let mut jumps = config.phase_jumps.clone();
+    jumps.sort_by_key(|&(at, _)| at);
+    let mut jumps = jumps.into_iter().peekable();
+
+    let step = TAU / f64::from(config.sample_rate);
+    let mut phase = 0f64;
+    let mut out = Vec::with_capacity(len + 2 * config.padding);
+    for i in 0..len + 2 * config.padding {
+        while let Some((_, radians)) = jumps.next_if(|&(at, _)| at <= i) {
+            phase += radians;
+        }
+
+        let carrier = match i.checked_sub(config.padding) {
+            Some(i) if i < len => {
+                let t = i as f64 / f64::from(config.sample_rate);
+                let hz =
+                    config.offset + config.drift * t + f64::from(config.deviation) * frequency[i];
+                phase = (phase + step * hz) % TAU;
+                Complex32::from_polar(config.amplitude, phase as f32)
+            }
+            _ => Complex32::new(0., 0.),
+        };
+
+        out.push(carrier + Complex32::new(rng.gaussian(), rng.gaussian()) * config.noise);
+    }
+    out
+}
+
+/// smooth the transitions, as the Si4432 does in GFSK mode
+fn gaussian_filter(nrz: &[f64], bt: f64, samples_per_bit: f64) -> Vec<f64> {
+    // standard deviation of the impulse response, in samples
+    let sigma = LN_2.sqrt() / (TAU * bt) * samples_per_bit;
+    let half = (3. * sigma).ceil() as isize;
+    let taps = (-half..=half)
+        .map(|i| (-(i as f64).powi(2) / (2. * sigma * sigma)).exp())
+        .collect::<Vec<f64>>();
+    let total = taps.iter().sum::<f64>();
+
+    (0..nrz.len() as isize)
+        .map(|i| {
+            taps.iter()
+                .enumerate()
+                .map(|(j, tap)| {
+                    let k = (i + j as isize - half).clamp(0, nrz.len() as isize - 1);
+                    tap * nrz[k as usize]
+                })
+                .sum::<f64>()
+                / total
+        })
+        .collect()
+}
+
+/// as an rtl-sdr would produce, clipping
+pub fn to_cu8(samples: &[Complex32]) -> Vec<u8> {
+    samples
+        .iter()
+        .flat_map(|v| [v.re, v.im])
+        .map(|v| (v * 128. + 128.).round().clamp(0., 255.) as u8)
+        .collect()
+}
+
+pub fn to_cf32(samples: &[Complex32]) -> Vec<u8> {
+    samples
+        .iter()
+        .flat_map(|v| [v.re, v.im])
+        .flat_map(f32::to_le_bytes)
+        .collect()
+}
+
+/// splitmix64; small, and the same everywhere, so seeded tests are reproducible
+pub struct Rng(u64);
+
+impl Rng {
+    pub fn new(seed: u64) -> Rng {
+        Rng(seed)
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    /// in [0, 1)
+    pub fn uniform(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    /// standard normal, by Box-Muller
+    pub fn gaussian(&mut self) -> f32 {
+        let u = 1. - self.uniform();
+        let v = self.uniform();
+        ((-2. * u.ln()).sqrt() * (TAU * v).cos()) as f32
+    }
+}
+
+#[test]
+fn test_modulate_demodulates() {
+    use crate::demod_fm::FmDemod;
+
+    for bt in [None, Some(0.5)] {
+        let config = Config {
+            sample_rate: 1_000_000,
+            deviation: 50_000,
+            offset: 100_000.,
+            bt,
+            padding: 0,
+            noise: 0.,
+            ..Config::default()
+        };
+        let samples = modulate(&[0b1100_0000], &config);
+        assert_eq!(samples.len(), 800);
+
+        // the middle of the first bit, and the middle of the last bit
+        let mut demod = FmDemod::new(50_000, 1_000_000);
+        let fm = samples
+            .iter()
+            .map(|&v| demod.update(v))
+            .collect::<Vec<f32>>();
+        assert!((fm[50] - 3.).abs() < 0.01, "{bt:?}: {}", fm[50]);
+        assert!((fm[750] - 1.).abs() < 0.01, "{bt:?}: {}", fm[750]);
+    }
+}
+
+#[test]
+fn test_file_formats() {
+    use crate::iq::{Format, IqReader};
+    use crate::source::SampleSource;
+
+    let samples = modulate(b"\x0f", &Config::default());
+    for (bytes, format, tolerance) in [
+        (to_cu8(&samples), Format::Cu8, 0.01),
+        (to_cf32(&samples), Format::Cf32, 0.),
+    ] {
+        let mut reader = IqReader::new(bytes.as_slice(), format).expect("no header");
+        let mut read = vec![Complex32::new(0., 0.); samples.len()];
+        assert_eq!(
+            reader.read_samples(&mut read).expect("in-memory"),
+            samples.len()
+        );
+        for (a, b) in samples.iter().zip(&read) {
+            assert!((a - b).norm() <= tolerance, "{format:?}: {a} != {b}");
+        }
+    }
+}
+
+#[test]
+fn test_rng() {
+    let mut rng = Rng::new(7);
+    let values = (0..10_000).map(|_| rng.gaussian()).collect::<Vec<f32>>();
+    let mean = values.iter().sum::<f32>() / values.len() as f32;
+    let var = values.iter().map(|v| (v - mean).powi(2)).sum::<f32>() / values.len() as f32;
+    assert!(mean.abs() < 0.05, "{mean}");
+    assert!((var - 1.).abs() < 0.05, "{var}");
+
+    assert_eq!(Rng::new(7).next_u64(), Rng::new(7).next_u64());
+}
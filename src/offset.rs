@@ -0,0 +1,315 @@
+//! find the ShineLink in a capture, rather than trusting the shift we were told
+//!
+//! Each block of the capture is FFTed, and the blocks with a burst in them are averaged, a
+//! window at a time, and the carrier is between the two FSK tones in that. Over a long capture,
+//! each window's estimate shows how the dongle (or the ShineLink) drifts.
+
+use crate::source::SampleSource;
+use crate::squelch::Config;
+use anyhow::Result;
+use itertools::Itertools;
+use num_complex::Complex32;
+use rustfft::FftPlanner;
+use std::f32::consts::TAU;
+
+/// samples per FFT
+const BLOCK: usize = 4096;
+/// how much stronger than the median bin a block's strongest bin must be for it to have a burst
+const HOT: f32 = 20.;
+/// fewer blocks than this in a window is too little to trust
+const MIN_HOT_BLOCKS: usize = 4;
+/// the dongle's DC spike (Hz), which is never the ShineLink
+const DC: f64 = 5_000.;
+/// the decoder assumes 100us bits
+const BIT_RATE: f64 = 10_000.;
+/// where we usually tune (Hz)
+pub const FREQUENCY: f64 = 434_200_000.;
+/// where the ShineLink usually is, when we tune there, as `squelch::Config::shift` (Hz)
+pub const SHIFT: f64 = 476_000.;
+
+/// where the carrier was, over one window of the capture
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Estimate {
+    /// the first sample of the window
+    pub at: usize,
+    /// as `squelch::Config`: the negation of where the carrier is (Hz)
+    pub shift: f64,
+    /// of the strongest bin, over the median bin (dB)
+    pub snr: f32,
+    /// how many blocks had a burst in them
+    pub blocks: usize,
+}
+
+/// the estimates for each window of a capture which had any bursts in it
+#[derive(Debug, Clone, PartialEq)]
+pub struct Track {
+    pub sample_rate: u32,
+    pub estimates: Vec<Estimate>,
+}
+
+impl Track {
+    /// the median of the estimates, if there are any
+    pub fn shift(&self) -> Option<f64> {
+        let shifts = self
+            .estimates
+            .iter()
+            .map(|e| e.shift)
+            .sorted_by(f64::total_cmp)
+            .collect_vec();
+        shifts.get(shifts.len() / 2).copied()
+    }
+
+    /// how fast the shift is changing (Hz/s), by least squares, if there are two windows
+    pub fn drift(&self) -> Option<f64> {
+        if self.estimates.len() < 2 {
+            return None;
+        }
+        let points = self
+            .estimates
+            .iter()
+            .map(|e| (e.at as f64 / f64::from(self.sample_rate), e.shift))
+            .collect_vec();
+        let n = points.len() as f64;
+        let mean_t = points.iter().map(|p| p.0).sum::<f64>() / n;
+        let mean_s = points.iter().map(|p| p.1).sum::<f64>() / n;
+        let var = points.iter().map(|p| (p.0 - mean_t).powi(2)).sum::<f64>();
+        let cov = points
+            .iter()
+            .map(|p| (p.0 - mean_t) * (p.1 - mean_s))
+            .sum::<f64>();
+        (var > 0.).then(|| cov / var)
+    }
+}
+
+/// how far off (parts per million of `frequency`) the dongle's tuning is, if the signal was
+/// expected at `expected` but found at `shift`; positive if the dongle reads high
+pub fn ppm(shift: f64, expected: f64, frequency: f64) -> f64 {
+    (shift - expected) / frequency * 1e6
+}
+
+/// Estimate where the ShineLink is, every `window` seconds of the source, which is read to the
+/// end. Only `config.sample_rate` and `config.deviation` are used.
+pub fn track(source: &mut impl SampleSource, config: &Config, window: f64) -> Result<Track> {
+    let sample_rate = f64::from(config.sample_rate);
+    let blocks_per_window = ((window * sample_rate / BLOCK as f64).round() as usize).max(1);
+    let fft = FftPlanner::<f32>::new().plan_fft_forward(BLOCK);
+    let hann = (0..BLOCK)
+        .map(|i| 0.5 - 0.5 * (TAU * i as f32 / BLOCK as f32).cos())
+        .collect_vec();
+    let dc_bins = (DC / sample_rate * BLOCK as f64).ceil() as usize;
+
+    let mut estimates = Vec::new();
+    let mut sum = vec![0f32; BLOCK];
+    let mut hot = 0;
+    let mut block = vec![Complex32::new(0., 0.); BLOCK];
+    let mut filled = 0;
+    let mut n = 0;
+    loop {
+        let len = source.read_samples(&mut block[filled..])?;
+        filled += len;
+        if filled < BLOCK {
+            if len == 0 {
+                break;
+            }
+            continue;
+        }
+        filled = 0;
+
+        for (v, w) in block.iter_mut().zip(&hann) {
+            *v *= w;
+        }
+        fft.process(&mut block);
+        // in frequency order, so the signal never wraps around
+        let power = block[BLOCK / 2..]
+            .iter()
+            .chain(&block[..BLOCK / 2])
+            .map(|v| v.norm_sqr())
+            .collect_vec();
+        let floor = median(&power);
+        let peak = power
+            .iter()
+            .enumerate()
+            .filter(|(i, _)| i.abs_diff(BLOCK / 2) > dc_bins)
+            .map(|(_, &p)| p)
+            .fold(0f32, f32::max);
+        if peak > floor * HOT {
+            hot += 1;
+            for (s, p) in sum.iter_mut().zip(&power) {
+                *s += p;
+            }
+        }
+
+        n += 1;
+        if n % blocks_per_window == 0 {
+            let at = (n - blocks_per_window) * BLOCK;
+            estimates.extend(estimate(&sum, hot, at, config, dc_bins));
+            sum.fill(0.);
+            hot = 0;
+        }
+    }
+    let at = (n - n % blocks_per_window) * BLOCK;
+    estimates.extend(estimate(&sum, hot, at, config, dc_bins));
+
+    Ok(Track {
+        sample_rate: config.sample_rate,
+        estimates,
+    })
+}
+
+/// the carrier, from the summed spectra of the `hot` blocks of a window
+fn estimate(
+    sum: &[f32],
+    hot: usize,
+    at: usize,
+    config: &Config,
+    dc_bins: usize,
+) -> Option<Estimate> {
+    if hot < MIN_HOT_BLOCKS {
+        return None;
+    }
+    let hz_per_bin = f64::from(config.sample_rate) / BLOCK as f64;
+    let floor = median(sum);
+    let excess = sum
+        .iter()
+        .enumerate()
+        .map(|(i, &p)| {
+            if i.abs_diff(BLOCK / 2) <= dc_bins {
+                0.
+            } else {
+                (p - floor).max(0.)
+            }
+        })
+        .collect_vec();
+    let (peak, &strongest) = excess
+        .iter()
+        .enumerate()
+        .max_by(|a, b| a.1.total_cmp(b.1))?;
+
+    // There are usually more zeros than ones (the key is mostly uppercase), and a lone one never
+    // quite reaches the deviation, so the tones differ in power and shape, but they're still
+    // either side of the carrier: it's where the spectrum best matches its mirror image. The
+    // strongest bin is in one of the tones, so the carrier is within a deviation of it.
+    let span = ((f64::from(config.deviation) + BIT_RATE) / hz_per_bin).ceil() as usize;
+    // pairs of bins closer together than this are in the same tone, which is symmetric itself
+    let gap = (BIT_RATE / hz_per_bin).ceil() as usize;
+    let mirrored = |twice: usize| {
+        (twice.saturating_sub(BLOCK - 1)..(twice / 2).saturating_sub(gap))
+            .map(|i| f64::from(excess[i]) * f64::from(excess[twice - i]))
+            .sum::<f64>()
+    };
+    let symmetry = (2 * peak.saturating_sub(span)..2 * (peak + span).min(BLOCK - 1))
+        .map(|twice| (twice, mirrored(twice)))
+        .collect_vec();
+    let best = symmetry
+        .iter()
+        .position_max_by(|a, b| a.1.total_cmp(&b.1))?;
+    // between half bins, by a parabola through the best and its neighbours
+    let nudge = match (best.checked_sub(1), symmetry.get(best + 1)) {
+        (Some(before), Some(&(_, after))) => {
+            let (before, at) = (symmetry[before].1, symmetry[best].1);
+            let curve = before - 2. * at + after;
+            if curve < 0. {
+                (before - after) / (2. * curve)
+            } else {
+                0.
+            }
+        }
+        _ => 0.,
+    };
+    let centre = (symmetry[best].0 as f64 + nudge) / 2.;
+
+    Some(Estimate {
+        at,
+        shift: -(centre - (BLOCK / 2) as f64) * hz_per_bin,
+        snr: 10. * ((strongest + floor) / floor).log10(),
+        blocks: hot,
+    })
+}
+
+fn median(values: &[f32]) -> f32 {
+    let mut sorted = values.to_vec();
+    let mid = sorted.len() / 2;
+    *sorted.select_nth_unstable_by(mid, f32::total_cmp).1
+}
+
+#[cfg(test)]
+fn test_capture(offsets: &[f64], gap: usize) -> Vec<Complex32> {
+    use crate::encode::encode;
+    use crate::frame::Frame;
+    use crate::modulate::{self, modulate};
+
+    let frame = Frame::parse(b"RF9\x01\x00\x10KWK1CGQ11AHZL0CGQ11A\x03\x00\x04\x00\x01\x02\x03")
+        .expect("valid frame");
+    offsets
+        .iter()
+        .enumerate()
+        .flat_map(|(seed, &offset)| {
+            modulate(
+                &encode(&frame),
+                &modulate::Config {
+                    offset,
+                    bt: Some(0.5),
+                    padding: gap,
+                    seed: seed as u64,
+                    ..modulate::Config::default()
+                },
+            )
+        })
+        .collect()
+}
+
+#[cfg(test)]
+fn test_config() -> Config {
+    Config {
+        decimation: 16,
+        sample_rate: 2_880_000,
+        deviation: 60_000,
+        shift: 0.,
+        filter: None,
+        energy: None,
+    }
+}
+
+#[test]
+fn test_track() {
+    // the carrier 1kHz from where we expected it, a DC spike, and a burst every 0.2s
+    let mut capture = test_capture(&[-475_000.; 5], 290_000);
+    for v in &mut capture {
+        *v += Complex32::new(0.05, 0.02);
+    }
+    let track = track(&mut capture.as_slice(), &test_config(), 10.).expect("in memory");
+    assert_eq!(track.estimates.len(), 1);
+    let shift = track.shift().expect("found");
+    assert!((shift - 475_000.).abs() < 300., "{track:?}");
+    assert!(track.estimates[0].snr > 20., "{track:?}");
+    assert!(
+        (ppm(shift, 476_000., FREQUENCY) + 2.3).abs() < 2.5,
+        "{shift}"
+    );
+    assert_eq!(track.drift(), None);
+
+    // nothing in noise alone
+    let mut rng = crate::modulate::Rng::new(1);
+    let noise = (0..BLOCK * 20)
+        .map(|_| Complex32::new(rng.gaussian(), rng.gaussian()) * 0.01)
+        .collect_vec();
+    let quiet = self::track(&mut noise.as_slice(), &test_config(), 10.).expect("in memory");
+    assert_eq!(quiet.shift(), None);
+}
+
+#[test]
+fn test_track_drift() {
+    // the carrier creeping up 2kHz a window
+    let offsets = (0..6).map(|i| 250_000. + 2_000. * i as f64).collect_vec();
+    let capture = test_capture(&offsets, 150_000);
+    let window = capture.len() as f64 / 6. / 2_880_000.;
+    let track = track(&mut capture.as_slice(), &test_config(), window).expect("in memory");
+    assert!(track.estimates.len() >= 5, "{track:?}");
+    for e in &track.estimates {
+        let i = (e.at as f64 / (window * 2_880_000.)).round();
+        let expected = -(250_000. + 2_000. * i);
+        assert!((e.shift - expected).abs() < 300., "{track:?}");
+    }
+    let drift = track.drift().expect("several windows") * window;
+    assert!((drift + 2_000.).abs() < 100., "{drift}");
+}
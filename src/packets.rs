@@ -0,0 +1,206 @@
+//! decoders for the payloads of specific packet types, i.e. `Frame::payload`
+//!
+//! Nothing here is documented anywhere; see the README for how little we know.
+
+pub mod flags;
+pub mod t156;
+pub mod t172;
+pub mod t33;
+pub mod t60;
+
+use anyhow::{Result, ensure};
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Payload {
+    /// 172
+    Telemetry(t172::Telemetry),
+    /// 60
+    Serials(t60::Serials),
+    /// 33
+    Clock(t33::Clock),
+    /// 13 and 14
+    Flags(flags::Flags),
+    /// 156
+    Metadata(t156::Metadata),
+    /// a type we don't know, or a payload which didn't parse as its type (e.g. a request)
+    Unknown { ty: u16, bytes: Vec<u8> },
+}
+
+impl Payload {
+    pub fn parse(ty: u16, bytes: &[u8]) -> Payload {
+        let parsed = match ty {
+            13 | 14 => flags::Flags::parse(ty, bytes).map(Payload::Flags),
+            33 => t33::Clock::parse(bytes).map(Payload::Clock),
+            60 => t60::Serials::parse(bytes).map(Payload::Serials),
+            156 => t156::Metadata::parse(bytes).map(Payload::Metadata),
+            172 => t172::Telemetry::parse(bytes).map(Payload::Telemetry),
+            _ => Err(anyhow::anyhow!("unknown packet type")),
+        };
+
+        parsed.unwrap_or_else(|_| Payload::Unknown {
+            ty,
+            bytes: bytes.to_vec(),
+        })
+    }
+}
+
+/// a wall-clock time, in whatever time zone the device thinks it's in
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct LocalTime {
+    pub year: u16,
+    pub month: u8,
+    pub day: u8,
+    pub hour: u8,
+    pub minute: u8,
+    pub second: u8,
+}
+
+impl LocalTime {
+    /// `2025-07-19 14:43:19`, as the devices send it, or `2025-07-19T14_43_19`,
+    /// as rtl-sdr-snipper names its captures
+    pub fn parse(s: &[u8]) -> Option<LocalTime> {
+        if s.len() != 19 || !matches!(s[10], b' ' | b'T') {
+            return None;
+        }
+        let date = LocalTime::parse_date(&s[..10])?;
+        let time_sep = s[13];
+        if !matches!(time_sep, b':' | b'_') || s[16] != time_sep {
+            return None;
+        }
+
+        let t = LocalTime {
+            hour: digits(&s[11..13])? as u8,
+            minute: digits(&s[14..16])? as u8,
+            second: digits(&s[17..19])? as u8,
+            ..date
+        };
+        (t.hour < 24 && t.minute < 60 && t.second <= 60).then_some(t)
+    }
+
+    /// `2025-07-19`, at midnight
+    pub fn parse_date(s: &[u8]) -> Option<LocalTime> {
+        if s.len() != 10 || s[4] != b'-' || s[7] != b'-' {
+            return None;
+        }
+        let t = LocalTime {
+            year: digits(&s[..4])? as u16,
+            month: digits(&s[5..7])? as u8,
+            day: digits(&s[8..10])? as u8,
+            hour: 0,
+            minute: 0,
+            second: 0,
+        };
+        ((1..=12).contains(&t.month) && (1..=31).contains(&t.day)).then_some(t)
+    }
+
+    /// seconds since 1970-01-01 00:00:00, in the same (unknown) time zone; for comparing times
+    pub fn seconds_since_epoch(&self) -> i64 {
+        // Howard Hinnant's days_from_civil
+        let (m, d) = (i64::from(self.month), i64::from(self.day));
+        let y = i64::from(self.year) - i64::from(m <= 2);
+        let era = y.div_euclid(400);
+        let yoe = y - era * 400;
+        let doy = (153 * (m + if m > 2 { -3 } else { 9 }) + 2) / 5 + d - 1;
+        let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+        let days = era * 146097 + doe - 719468;
+
+        days * 86400
+            + i64::from(self.hour) * 3600
+            + i64::from(self.minute) * 60
+            + i64::from(self.second)
+    }
+}
+
+impl fmt::Display for LocalTime {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:04}-{:02}-{:02} {:02}:{:02}:{:02}",
+            self.year, self.month, self.day, self.hour, self.minute, self.second
+        )
+    }
+}
+
+fn digits(s: &[u8]) -> Option<u32> {
+    s.iter().try_fold(0, |acc, &c| {
+        c.is_ascii_digit().then(|| acc * 10 + u32::from(c - b'0'))
+    })
+}
+
+/// split a payload into big-endian `i32` columns, as the inverter seems to think in them
+fn be_i32s(payload: &[u8], expected_len: usize) -> Result<Vec<i32>> {
+    ensure_len(payload, expected_len)?;
+    Ok(payload
+        .chunks_exact(4)
+        .map(|v| i32::from_be_bytes(v.try_into().expect("chunks_exact")))
+        .collect())
+}
+
+fn ensure_len(payload: &[u8], expected_len: usize) -> Result<()> {
+    ensure!(
+        payload.len() == expected_len,
+        "expected {expected_len} bytes, not {}",
+        payload.len()
+    );
+    Ok(())
+}
+
+/// a fixed-width, null (or space) padded ascii field; `None` if it's empty
+fn ascii_field(field: &[u8]) -> Result<Option<String>> {
+    let trimmed = field
+        .iter()
+        .rposition(|&v| v != 0 && v != b' ')
+        .map_or(&field[..0], |end| &field[..=end]);
+    if trimmed.is_empty() {
+        return Ok(None);
+    }
+    ensure!(
+        trimmed.iter().all(|v| v.is_ascii_graphic() || *v == b' '),
+        "non-ascii field: {}",
+        crate::unambiguous(field)
+    );
+    Ok(Some(
+        String::from_utf8(trimmed.to_vec()).expect("checked ascii"),
+    ))
+}
+
+#[test]
+fn test_local_time() {
+    let device = LocalTime::parse(b"2025-07-19 14:43:19").expect("valid");
+    assert_eq!(device.to_string(), "2025-07-19 14:43:19");
+
+    let capture = LocalTime::parse(b"2025-07-19T14_52_29").expect("valid");
+    assert_eq!(
+        capture.seconds_since_epoch() - device.seconds_since_epoch(),
+        550
+    );
+    assert!(capture > device);
+
+    assert_eq!(
+        LocalTime::parse(b"1970-01-02 00:00:01")
+            .expect("valid")
+            .seconds_since_epoch(),
+        86401
+    );
+    assert_eq!(LocalTime::parse(b"2025-13-19 14:43:19"), None);
+    assert_eq!(LocalTime::parse(b"2025-07-19 14:43_19"), None);
+}
+
+#[test]
+fn test_payload_fallback() {
+    assert_eq!(
+        Payload::parse(172, &[1, 0]),
+        Payload::Unknown {
+            ty: 172,
+            bytes: vec![1, 0]
+        }
+    );
+    assert_eq!(
+        Payload::parse(99, &[1, 2, 3]),
+        Payload::Unknown {
+            ty: 99,
+            bytes: vec![1, 2, 3]
+        }
+    );
+}
@@ -0,0 +1,57 @@
+//! packet types 13 and 14: a few bytes, mostly zeros; flags?
+
+use super::ensure_len;
+use anyhow::{Result, bail};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Flags {
+    pub ty: u16,
+    /// the payload, untouched, as we don't know what any of it means
+    pub bytes: Vec<u8>,
+}
+
+impl Flags {
+    /// only the lengths we've seen: four bytes for a 14, three for a 13; anything else (e.g. a
+    /// request) isn't flags
+    pub fn parse(ty: u16, payload: &[u8]) -> Result<Flags> {
+        let len = match ty {
+            13 => 3,
+            14 => 4,
+            other => bail!("type {other} isn't flags"),
+        };
+        ensure_len(payload, len)?;
+        Ok(Flags {
+            ty,
+            bytes: payload.to_vec(),
+        })
+    }
+
+    /// the bytes which are set, by position
+    pub fn set(&self) -> impl Iterator<Item = (usize, u8)> + '_ {
+        self.bytes
+            .iter()
+            .enumerate()
+            .filter(|(_, v)| **v != 0)
+            .map(|(i, v)| (i, *v))
+    }
+}
+
+#[test]
+fn test_parse() {
+    use super::Payload;
+
+    let flags = Flags::parse(14, &[0, 1, 0, 0x20]).expect("valid");
+    assert_eq!(flags.set().collect::<Vec<_>>(), [(1, 1), (3, 0x20)]);
+    assert!(Flags::parse(13, &[0, 0, 4]).is_ok());
+
+    // a request, or nothing at all, isn't flags
+    assert_eq!(
+        Payload::parse(14, &[1, 0]),
+        Payload::Unknown {
+            ty: 14,
+            bytes: vec![1, 0]
+        }
+    );
+    assert!(Flags::parse(13, &[]).is_err());
+    assert!(Flags::parse(13, &[0, 0, 0, 4]).is_err());
+}
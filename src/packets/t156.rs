@@ -0,0 +1,65 @@
+//! packet type 156: metadata; hardware versions, standards(?), install date, ...
+//!
+//! The layout is unknown, so this just pulls out anything that looks like text.
+
+use super::{LocalTime, ensure_len};
+use anyhow::Result;
+
+pub const LEN: usize = 156;
+
+/// ignore shorter runs of printable bytes, which are usually just numbers that happen to be printable
+const MIN_STRING_LEN: usize = 3;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Metadata {
+    /// runs of printable ascii, in order
+    pub strings: Vec<String>,
+    /// the first thing that looks like a date (or date and time)
+    pub install_date: Option<LocalTime>,
+    pub bytes: Vec<u8>,
+}
+
+impl Metadata {
+    pub fn parse(payload: &[u8]) -> Result<Metadata> {
+        ensure_len(payload, LEN)?;
+
+        let strings = payload
+            .split(|v| !v.is_ascii_graphic() && *v != b' ')
+            .map(|s| s.trim_ascii())
+            .filter(|s| s.len() >= MIN_STRING_LEN)
+            .map(|s| String::from_utf8(s.to_vec()).expect("checked ascii"))
+            .collect();
+
+        let install_date = payload
+            .windows(19)
+            .find_map(LocalTime::parse)
+            .or_else(|| payload.windows(10).find_map(LocalTime::parse_date));
+
+        Ok(Metadata {
+            strings,
+            install_date,
+            bytes: payload.to_vec(),
+        })
+    }
+}
+
+#[test]
+fn test_parse() {
+    // laid out as the README describes: versions and standards, and an install date
+    let mut payload = vec![0u8; LEN];
+    payload[4..12].copy_from_slice(b"GH1.0 PV");
+    payload[20..23].copy_from_slice(&[0x01, 0x41, 0x02]);
+    payload[40..49].copy_from_slice(b"VDE0126-1");
+    payload[80..99].copy_from_slice(b"2021-03-04 10:11:12");
+
+    let metadata = Metadata::parse(&payload).expect("valid");
+    assert_eq!(
+        metadata.strings,
+        ["GH1.0 PV", "VDE0126-1", "2021-03-04 10:11:12"]
+    );
+    assert_eq!(
+        metadata.install_date.map(|t| t.to_string()).as_deref(),
+        Some("2021-03-04 10:11:12")
+    );
+    assert!(Metadata::parse(&payload[..LEN - 1]).is_err());
+}
@@ -0,0 +1,128 @@
+//! packet type 172: energy and power telemetry, sent every five minutes
+//!
+//! The payload is 43 big-endian `i32`s. Only the first phase has ever been seen with values;
+//! the others are presumably for three-phase installs.
+//!
+//! Fields documented as "guessed" were inferred from their magnitudes and how they move
+//! together, not from any documentation or comparison with the app.
+
+use super::be_i32s;
+use anyhow::Result;
+
+pub const LEN: usize = 172;
+/// the columns we haven't a guess for, all zero in every capture so far, except for 30, which is
+/// always 20
+pub const UNKNOWN_COLUMNS: [usize; 12] = [27, 28, 29, 30, 32, 34, 37, 38, 39, 40, 41, 42];
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Telemetry {
+    /// columns 0..4, `[0, 1, 79, 132]` in every capture so far
+    pub header: [i32; 4],
+    /// guessed: per phase, volts; about 240
+    pub voltage: [f32; 3],
+    /// guessed: per phase, amps
+    pub current: [f32; 3],
+    /// guessed: per phase, watts; negative when exporting(?)
+    pub active_power: [f32; 3],
+    /// guessed: per phase, volt-amps; tracks voltage * current
+    pub apparent_power: [f32; 3],
+    /// guessed: per phase, volt-amps reactive
+    pub reactive_power: [f32; 3],
+    /// guessed: per phase, -1..=1; tracks active / apparent power
+    pub power_factor: [f32; 3],
+    /// watts
+    pub total_active_power: f32,
+    /// guessed: volt-amps
+    pub total_apparent_power: f32,
+    /// guessed: volt-amps reactive
+    pub total_reactive_power: f32,
+    /// guessed: -1..=1
+    pub total_power_factor: f32,
+    /// guessed: grid frequency, hertz; about 50
+    pub frequency: f32,
+    /// guessed: increasing, kWh since some date; doesn't line up with the app
+    pub import_energy: f32,
+    /// guessed: increasing, kWh since some date
+    pub export_energy: f32,
+    /// guessed: increasing, kWh; always a little more than `import_energy`
+    pub total_import_energy: f32,
+    /// guessed: increasing, kWh; always equal to `export_energy` so far
+    pub total_export_energy: f32,
+    /// the `UNKNOWN_COLUMNS`, as they are
+    pub unknown: [i32; UNKNOWN_COLUMNS.len()],
+}
+
+impl Telemetry {
+    pub fn parse(payload: &[u8]) -> Result<Telemetry> {
+        let c = be_i32s(payload, LEN)?;
+
+        let tenths = |i: usize| c[i] as f32 / 10.;
+        let thousandths = |i: usize| c[i] as f32 / 1000.;
+        let phases =
+            |i: usize, scale: &dyn Fn(usize) -> f32| [scale(i), scale(i + 1), scale(i + 2)];
+
+        Ok(Telemetry {
+            header: [c[0], c[1], c[2], c[3]],
+            voltage: phases(4, &tenths),
+            current: phases(7, &tenths),
+            active_power: phases(10, &tenths),
+            apparent_power: phases(13, &tenths),
+            reactive_power: phases(16, &tenths),
+            power_factor: phases(19, &thousandths),
+            total_active_power: tenths(22),
+            total_apparent_power: tenths(23),
+            total_reactive_power: tenths(24),
+            total_power_factor: thousandths(25),
+            frequency: tenths(26),
+            import_energy: tenths(31),
+            export_energy: tenths(33),
+            total_import_energy: tenths(35),
+            total_export_energy: tenths(36),
+            unknown: UNKNOWN_COLUMNS.map(|i| c[i]),
+        })
+    }
+}
+
+#[test]
+fn test_readme_sample() {
+    let t = Telemetry::parse(include_bytes!("../../172/2025-07-17T22_43_19.17023.pkt"))
+        .expect("valid sample");
+    assert_eq!(t.header, [0, 1, 79, 132]);
+    assert_eq!(t.voltage, [240.5, 0., 0.]);
+    assert_eq!(t.current, [1.7, 0., 0.]);
+    assert_eq!(t.active_power, [-139.4, 0., 0.]);
+    assert_eq!(t.apparent_power, [422.4, 0., 0.]);
+    assert_eq!(t.reactive_power, [-317.2, 0., 0.]);
+    assert_eq!(t.power_factor, [-0.336, 0., 0.]);
+    assert_eq!(t.total_active_power, -139.4);
+    assert_eq!(t.total_apparent_power, 422.4);
+    assert_eq!(t.total_reactive_power, -317.2);
+    assert_eq!(t.total_power_factor, -0.336);
+    assert_eq!(t.frequency, 49.9);
+    assert_eq!(t.import_energy, 378.5);
+    assert_eq!(t.export_energy, 104.6);
+    assert_eq!(t.total_import_energy, 380.5);
+    assert_eq!(t.total_export_energy, 104.6);
+    assert_eq!(t.unknown, [0, 0, 0, 20, 0, 0, 0, 0, 0, 0, 0, 0]);
+}
+
+#[test]
+fn test_all_samples() {
+    use std::fs;
+
+    let dir = concat!(env!("CARGO_MANIFEST_DIR"), "/172");
+    let mut seen = 0;
+    for f in fs::read_dir(dir).expect("samples dir") {
+        let path = f.expect("dir entry").path();
+        let t = Telemetry::parse(&fs::read(&path).expect("readable"))
+            .unwrap_or_else(|e| panic!("{}: {e}", path.display()));
+
+        assert!((49.5..=50.5).contains(&t.frequency), "{t:?}");
+        assert!((200. ..=260.).contains(&t.voltage[0]), "{t:?}");
+        assert!((-1. ..=1.).contains(&t.total_power_factor), "{t:?}");
+        seen += 1;
+    }
+    assert!(seen > 90);
+
+    assert!(Telemetry::parse(&[0; LEN - 4]).is_err());
+}
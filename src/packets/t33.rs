@@ -0,0 +1,44 @@
+//! packet type 33: the serial of the inverter again, and its idea of the local time
+
+use super::{LocalTime, ascii_field, ensure_len};
+use anyhow::{Context, Result};
+
+pub const LEN: usize = 33;
+const SERIAL_LEN: usize = 10;
+const TIME_LEN: usize = 19;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Clock {
+    pub serial: Option<String>,
+    /// e.g. `2025-07-19 14:43:19`; compare with the capture time for drift
+    pub time: LocalTime,
+}
+
+impl Clock {
+    pub fn parse(payload: &[u8]) -> Result<Clock> {
+        ensure_len(payload, LEN)?;
+        let serial = ascii_field(&payload[..SERIAL_LEN])?;
+
+        // the exact position isn't known, so look for it after the serial
+        let time = payload[SERIAL_LEN..]
+            .windows(TIME_LEN)
+            .find_map(LocalTime::parse)
+            .context("no time found")?;
+
+        Ok(Clock { serial, time })
+    }
+}
+
+#[test]
+fn test_parse() {
+    let mut payload = b"AB12345678\0\0".to_vec();
+    payload.extend_from_slice(b"2025-07-19 14:43:19");
+    payload.resize(LEN, 0);
+
+    let clock = Clock::parse(&payload).expect("valid");
+    assert_eq!(clock.serial.as_deref(), Some("AB12345678"));
+    assert_eq!(clock.time.to_string(), "2025-07-19 14:43:19");
+
+    payload[14] = b'x';
+    assert!(Clock::parse(&payload).is_err());
+}
@@ -0,0 +1,45 @@
+//! packet type 60: the serial of the inverter, and space for more serials
+//!
+//! The most common packet, by far. Assumed to be six ten-character slots.
+
+use super::{ascii_field, ensure_len};
+use anyhow::Result;
+
+pub const LEN: usize = 60;
+const SLOT_LEN: usize = 10;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Serials {
+    /// the non-empty slots, in order
+    pub serials: Vec<String>,
+}
+
+impl Serials {
+    pub fn parse(payload: &[u8]) -> Result<Serials> {
+        ensure_len(payload, LEN)?;
+        let mut serials = Vec::with_capacity(1);
+        for slot in payload.chunks_exact(SLOT_LEN) {
+            if let Some(serial) = ascii_field(slot)? {
+                serials.push(serial);
+            }
+        }
+        Ok(Serials { serials })
+    }
+}
+
+#[test]
+fn test_parse() {
+    // laid out as the README describes: the inverter's serial, then empty slots
+    let mut payload = b"AB12345678".to_vec();
+    payload.resize(LEN, 0);
+    let serials = Serials::parse(&payload).expect("valid");
+    assert_eq!(serials.serials, ["AB12345678"]);
+
+    payload[20..30].copy_from_slice(b"CD8765    ");
+    let serials = Serials::parse(&payload).expect("valid");
+    assert_eq!(serials.serials, ["AB12345678", "CD8765"]);
+
+    payload[3] = 0xff;
+    assert!(Serials::parse(&payload).is_err());
+    assert!(Serials::parse(&[1, 0]).is_err());
+}
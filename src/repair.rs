@@ -0,0 +1,115 @@
+//! when a candidate almost decodes, guess which of its bits were wrong
+//!
+//! Long packets (e.g. the 172s) rarely arrive intact; usually one or two bits are lost to a
+//! glitch, and they're usually the ones the clock recovery was least sure about.
+
+use crate::bits_to_byte;
+use itertools::Itertools;
+
+/// how many of the least confident bits are considered for changing
+const WEAKEST: usize = 16;
+
+/// Flip up to `budget` of the least confident bits of `bytes`, or add or remove one of them, until
+/// `check` accepts the result. `confidence` is per bit, from 0 to 1, and the first byte is the
+/// length, which limits where we look.
+///
+/// Returns what `check` returned, and how many bits were changed.
+pub(crate) fn repair<T>(
+    bytes: &[u8],
+    confidence: &[f32],
+    budget: usize,
+    check: impl Fn(&[u8]) -> Option<T>,
+) -> Option<(T, usize)> {
+    let bits = bytes
+        .iter()
+        .flat_map(|byte| (0..8).rev().map(move |i| byte >> i & 1 == 1))
+        .collect_vec();
+    let end = bytes
+        .first()
+        .map_or(0, |&len| (1 + usize::from(len)) * 8)
+        .min(bits.len())
+        .min(confidence.len());
+    let weakest = (0..end)
+        .sorted_by(|&a, &b| confidence[a].total_cmp(&confidence[b]))
+        .take(WEAKEST)
+        .collect_vec();
+
+    let pack = |bits: &[bool]| bits.chunks_exact(8).map(bits_to_byte).collect_vec();
+
+    for flips in 1..=budget {
+        for positions in weakest.iter().combinations(flips) {
+            let mut attempt = bits.clone();
+            for &pos in positions {
+                attempt[pos] = !attempt[pos];
+            }
+            if let Some(found) = check(&pack(&attempt)) {
+                return Some((found, flips));
+            }
+        }
+
+        if flips > 1 {
+            continue;
+        }
+
+        // a run which was read a bit too short, or too long
+        for &pos in &weakest {
+            let mut longer = bits.clone();
+            longer.insert(pos, bits[pos]);
+            let mut shorter = bits.clone();
+            shorter.remove(pos);
+            for attempt in [longer, shorter] {
+                if let Some(found) = check(&pack(&attempt)) {
+                    return Some((found, 1));
+                }
+            }
+        }
+    }
+
+    None
+}
+
+#[cfg(test)]
+fn damaged(original: &[u8], weak: &[usize]) -> (Vec<u8>, Vec<f32>) {
+    let mut confidence = vec![1.; original.len() * 8];
+    for &pos in weak {
+        confidence[pos] = 0.1;
+    }
+    (original.to_vec(), confidence)
+}
+
+#[test]
+fn test_repair_flips() {
+    let original = b"\x05hello\x00";
+    let check = |v: &[u8]| (v == original).then_some(());
+
+    let (mut bytes, confidence) = damaged(original, &[12, 25, 33]);
+    bytes[1] ^= 0b0000_1000;
+    bytes[3] ^= 0b0100_0000;
+    assert_eq!(repair(&bytes, &confidence, 2, check), Some(((), 2)));
+    assert_eq!(repair(&bytes, &confidence, 1, check), None);
+
+    // but not bits we were sure of
+    let (mut bytes, confidence) = damaged(original, &[12]);
+    bytes[4] ^= 1;
+    assert_eq!(repair(&bytes, &confidence, 2, check), None);
+}
+
+#[test]
+fn test_repair_slips() {
+    let original = b"\x05hello\xff";
+    let check = |v: &[u8]| (v.get(..6) == Some(&original[..6])).then_some(());
+
+    // bit 20, in the middle of the 'e', lost, so everything after it is a bit early, and the
+    // run it was in, ending at bit 19, is short
+    let bits = original
+        .iter()
+        .flat_map(|byte| (0..8).rev().map(move |i| byte >> i & 1 == 1))
+        .enumerate()
+        .filter(|&(i, _)| i != 20)
+        .map(|(_, bit)| bit)
+        .collect_vec();
+    let bytes = bits.chunks_exact(8).map(bits_to_byte).collect_vec();
+    let (_, confidence) = damaged(&bytes, &[19]);
+    assert_ne!(bytes[..6], original[..6]);
+    assert_eq!(repair(&bytes, &confidence, 1, check), Some(((), 1)));
+}
@@ -0,0 +1,229 @@
+//! pair up requests from the ShineLanBox with responses from the ShineLink
+//!
+//! The ShineLanBox asks for a packet type, and the ShineLink responds, sometimes repeatedly,
+//! sometimes never. The response carries the same sequence number as the request.
+//!
+//! Offsets are in whatever units the caller likes, as long as they're consistent,
+//! e.g. the chunk numbers from `squelch`.
+
+use crate::frame::Frame;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Observed {
+    pub offset: usize,
+    pub frame: Frame,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Response {
+    /// the first time we saw this response
+    pub first: Observed,
+    /// how many identical copies we saw, including the first
+    pub copies: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Transaction {
+    pub request: Observed,
+    /// how many identical copies of the request we saw, including the first
+    pub request_copies: usize,
+    /// distinct responses, in the order they first arrived
+    pub responses: Vec<Response>,
+}
+
+impl Transaction {
+    /// from the request to the first response
+    pub fn latency(&self) -> Option<usize> {
+        self.responses
+            .first()
+            .map(|r| r.first.offset - self.request.offset)
+    }
+
+    fn close(self) -> Event {
+        if self.responses.is_empty() {
+            Event::Unanswered(self)
+        } else {
+            Event::Answered(self)
+        }
+    }
+
+    /// By sequence number, not packet type: in one-chat the response's type isn't the
+    /// request's.
+    fn answers(&self, frame: &Frame) -> bool {
+        self.request.frame.seq == frame.seq
+            && self.request.frame.lanbox_serial == frame.lanbox_serial
+            && self.request.frame.link_serial == frame.link_serial
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Event {
+    /// a request, and everything which answered it
+    Answered(Transaction),
+    /// a request which got no response before the timeout
+    Unanswered(Transaction),
+    /// a response we didn't see the request for
+    Unsolicited(Observed),
+}
+
+/// guessed: requests have no data, or `[01] [00]`
+pub fn is_request(frame: &Frame) -> bool {
+    frame.payload.is_empty() || frame.payload == [1, 0]
+}
+
+pub struct Tracker {
+    timeout: usize,
+    pending: Vec<Transaction>,
+}
+
+impl Tracker {
+    /// transactions are closed once `timeout` has passed since their request
+    pub fn new(timeout: usize) -> Tracker {
+        Tracker {
+            timeout,
+            pending: Vec::with_capacity(4),
+        }
+    }
+
+    /// add the next frame, which must be no earlier than previous frames;
+    /// returns the events which have completed
+    pub fn push(&mut self, offset: usize, frame: Frame) -> Vec<Event> {
+        let mut events = self.expire(offset);
+        let observed = Observed { offset, frame };
+
+        if is_request(&observed.frame) {
+            match self.pending.iter().position(|t| t.answers(&observed.frame)) {
+                Some(i) if self.pending[i].request.frame == observed.frame => {
+                    self.pending[i].request_copies += 1;
+                }
+                // a different request with the same sequence number: responses are to it now
+                found => {
+                    if let Some(i) = found {
+                        events.push(self.pending.remove(i).close());
+                    }
+                    self.pending.push(Transaction {
+                        request: observed,
+                        request_copies: 1,
+                        responses: Vec::with_capacity(1),
+                    });
+                }
+            }
+            return events;
+        }
+
+        let Some(transaction) = self.pending.iter_mut().find(|t| t.answers(&observed.frame)) else {
+            events.push(Event::Unsolicited(observed));
+            return events;
+        };
+
+        match transaction
+            .responses
+            .iter_mut()
+            .find(|r| r.first.frame == observed.frame)
+        {
+            Some(dupe) => dupe.copies += 1,
+            None => transaction.responses.push(Response {
+                first: observed,
+                copies: 1,
+            }),
+        }
+
+        events
+    }
+
+    /// the stream has ended; close everything that's still open
+    pub fn finish(mut self) -> Vec<Event> {
+        self.expire(usize::MAX)
+    }
+
+    fn expire(&mut self, now: usize) -> Vec<Event> {
+        let (expired, pending) = self
+            .pending
+            .drain(..)
+            .partition(|t| now.saturating_sub(t.request.offset) > self.timeout);
+        self.pending = pending;
+
+        expired.into_iter().map(Transaction::close).collect()
+    }
+}
+
+/// run a whole, time-ordered, stream through a `Tracker`
+pub fn transactions(
+    frames: impl IntoIterator<Item = (usize, Frame)>,
+    timeout: usize,
+) -> Vec<Event> {
+    let mut tracker = Tracker::new(timeout);
+    let mut events = Vec::new();
+    for (offset, frame) in frames {
+        events.extend(tracker.push(offset, frame));
+    }
+    events.extend(tracker.finish());
+    events
+}
+
+#[cfg(test)]
+fn one_chat(seq: u8, payload: &[u8]) -> Frame {
+    Frame {
+        seq,
+        version: [1, 0, 0x10],
+        lanbox_serial: "KWK1CGQ11A".to_string(),
+        link_serial: "HZL0CGQ11A".to_string(),
+        unknown: 3,
+        packet_type: payload.len() as u16,
+        payload: payload.to_vec(),
+    }
+}
+
+#[test]
+fn test_transactions() {
+    let events = transactions(
+        [
+            (100, one_chat(b'9', &[])),
+            (110, one_chat(b'9', &[2])),
+            (120, one_chat(b'9', &[2])),
+            (130, one_chat(b'9', &[3])),
+            (200, one_chat(b':', &[])),
+            (205, one_chat(b':', &[])),
+            (400, one_chat(b';', &[5])),
+            (500, one_chat(b'<', &[])),
+            (510, one_chat(b'<', &[6])),
+            // asked again for something else, before the timeout
+            (600, one_chat(b'=', &[])),
+            (610, one_chat(b'=', &[1, 0])),
+            (620, one_chat(b'=', &[7])),
+        ],
+        50,
+    );
+
+    assert_eq!(events.len(), 6);
+
+    let Event::Answered(first) = &events[0] else {
+        panic!("{:?}", events[0]);
+    };
+    assert_eq!(first.latency(), Some(10));
+    assert_eq!(first.request_copies, 1);
+    assert_eq!(
+        first
+            .responses
+            .iter()
+            .map(|r| (r.first.offset, r.copies))
+            .collect::<Vec<_>>(),
+        vec![(110, 2), (130, 1)]
+    );
+
+    let Event::Unanswered(second) = &events[1] else {
+        panic!("{:?}", events[1]);
+    };
+    assert_eq!(second.request_copies, 2);
+    assert_eq!(second.latency(), None);
+
+    assert!(matches!(&events[2], Event::Unsolicited(o) if o.offset == 400));
+    assert!(matches!(&events[3], Event::Answered(t) if t.latency() == Some(10)));
+
+    // the first is closed by the retry, which gets the response
+    assert!(matches!(&events[4], Event::Unanswered(t) if t.request.offset == 600));
+    let Event::Answered(retry) = &events[5] else {
+        panic!("{:?}", events[5]);
+    };
+    assert_eq!((retry.request.offset, retry.latency()), (610, Some(10)));
+}
@@ -0,0 +1,454 @@
+//! SigMF (https://sigmf.org) metadata, so recordings carry their own sample rate and shift
+//!
+//! A recording is a `.sigmf-meta` json file next to a `.sigmf-data` file of samples. The meta
+//! can instead name an existing file (`core:dataset`), so we can describe captures in place.
+
+use crate::iq::{Format, IqReader};
+use crate::squelch::{CHUNK_BY, Config, SMOOTHING, Signal};
+use anyhow::{Context, Result, bail};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::path::{Path, PathBuf};
+use std::{fs, io};
+
+pub const VERSION: &str = "1.2.0";
+
+/// for the real-valued demodulations `squelcher` writes
+pub const DEMODULATED: &str = "rf32_le";
+/// `core:recorder`, for the recordings we made, which we can rewrite
+pub const RECORDER: &str = "shinelink";
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Meta {
+    pub global: Global,
+    #[serde(default)]
+    pub captures: Vec<Capture>,
+    #[serde(default)]
+    pub annotations: Vec<Annotation>,
+    /// anything else, e.g. from other tools, so it survives a rewrite
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Global {
+    #[serde(rename = "core:datatype")]
+    pub datatype: String,
+    #[serde(
+        rename = "core:sample_rate",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub sample_rate: Option<f64>,
+    #[serde(rename = "core:version")]
+    pub version: String,
+    /// the data file, if it's not named after the meta file
+    #[serde(
+        rename = "core:dataset",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub dataset: Option<String>,
+    #[serde(
+        rename = "core:description",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub description: Option<String>,
+    #[serde(
+        rename = "core:recorder",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub recorder: Option<String>,
+    #[serde(
+        rename = "core:extensions",
+        default,
+        skip_serializing_if = "Vec::is_empty"
+    )]
+    pub extensions: Vec<Extension>,
+    /// as `squelch::Config`: the negation of where the ShineLink signal is, relative to the
+    /// centre frequency (Hz); e.g. 476000 for a signal 476kHz below it
+    #[serde(
+        rename = "shinelink:shift",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub shift: Option<f64>,
+    #[serde(
+        rename = "shinelink:deviation",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub deviation: Option<u32>,
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Extension {
+    pub name: String,
+    pub version: String,
+    pub optional: bool,
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Capture {
+    #[serde(rename = "core:sample_start")]
+    pub sample_start: u64,
+    #[serde(
+        rename = "core:frequency",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub frequency: Option<f64>,
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Annotation {
+    #[serde(rename = "core:sample_start")]
+    pub sample_start: u64,
+    #[serde(
+        rename = "core:sample_count",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub sample_count: Option<u64>,
+    #[serde(
+        rename = "core:label",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub label: Option<String>,
+    #[serde(
+        rename = "core:comment",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub comment: Option<String>,
+    #[serde(
+        rename = "shinelink:packet_type",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub packet_type: Option<u16>,
+    /// whether the burst decoded to a frame with a valid checksum
+    #[serde(
+        rename = "shinelink:crc",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub crc: Option<bool>,
+    /// the power in the channel, during the burst (dBFS)
+    #[serde(
+        rename = "shinelink:rssi",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub rssi: Option<f32>,
+    /// and over the noise floor (dB)
+    #[serde(
+        rename = "shinelink:snr",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub snr: Option<f32>,
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+impl Meta {
+    pub fn new(datatype: &str, sample_rate: u32) -> Meta {
+        Meta {
+            global: Global {
+                datatype: datatype.to_string(),
+                sample_rate: Some(f64::from(sample_rate)),
+                version: VERSION.to_string(),
+                dataset: None,
+                description: None,
+                recorder: Some(RECORDER.to_string()),
+                extensions: vec![Extension {
+                    name: "shinelink".to_string(),
+                    version: env!("CARGO_PKG_VERSION").to_string(),
+                    optional: true,
+                    extra: Map::new(),
+                }],
+                shift: None,
+                deviation: None,
+                extra: Map::new(),
+            },
+            captures: vec![Capture {
+                sample_start: 0,
+                frequency: None,
+                extra: Map::new(),
+            }],
+            annotations: Vec::new(),
+            extra: Map::new(),
+        }
+    }
+
+    /// whether we made this recording, so can rewrite it
+    pub fn is_ours(&self) -> bool {
+        self.global.recorder.as_deref() == Some(RECORDER)
+    }
+
+    /// replace the annotations we made last time with `annotations`, keeping anyone else's
+    pub fn annotate(&mut self, annotations: Vec<Annotation>) {
+        self.annotations.retain(|a| !a.is_ours());
+        self.annotations.extend(annotations);
+        self.annotations.sort_by_key(|a| a.sample_start);
+    }
+
+    /// describe a capture for `squelch`, with `dataset` naming the data file, if it's not ours
+    pub fn for_capture(format: Format, config: &Config, dataset: Option<String>) -> Result<Meta> {
+        let mut meta = Meta::new(datatype(format)?, config.sample_rate);
+        meta.global.dataset = dataset;
+        meta.global.shift = Some(config.shift);
+        meta.global.deviation = Some(config.deviation);
+        Ok(meta)
+    }
+
+    pub fn read(path: impl AsRef<Path>) -> Result<Meta> {
+        let path = path.as_ref();
+        let file = io::BufReader::new(fs::File::open(path)?);
+        serde_json::from_reader(file).with_context(|| format!("parsing {}", path.display()))
+    }
+
+    pub fn write(&self, path: impl AsRef<Path>) -> Result<()> {
+        let mut json = serde_json::to_string_pretty(self)?;
+        json.push('\n');
+        fs::write(path, json)?;
+        Ok(())
+    }
+
+    pub fn format(&self) -> Result<Format> {
+        Ok(match self.global.datatype.as_str() {
+            "cu8" => Format::Cu8,
+            "ci8" => Format::Cs8,
+            "ci16_le" => Format::Cs16,
+            "cf32_le" => Format::Cf32,
+            other => bail!("unsupported sigmf datatype: {other:?}"),
+        })
+    }
+
+    /// the recording's settings, where it has them
+    pub fn config(&self, defaults: &Config) -> Config {
+        Config {
+            decimation: defaults.decimation,
+            sample_rate: self
+                .global
+                .sample_rate
+                .map_or(defaults.sample_rate, |v| v.round() as u32),
+            deviation: self.global.deviation.unwrap_or(defaults.deviation),
+            shift: self.global.shift.unwrap_or(defaults.shift),
+            filter: defaults.filter,
+            energy: defaults.energy,
+        }
+    }
+}
+
+/// the sigmf name for a sample format; wav isn't a sample format
+pub fn datatype(format: Format) -> Result<&'static str> {
+    Ok(match format {
+        Format::Cu8 => "cu8",
+        Format::Cs8 => "ci8",
+        Format::Cs16 => "ci16_le",
+        Format::Cf32 => "cf32_le",
+        Format::Wav => bail!("wav files can't be described by sigmf"),
+    })
+}
+
+/// a `.sigmf-meta` or `.sigmf-data` file
+pub fn is_sigmf(path: impl AsRef<Path>) -> bool {
+    matches!(
+        path.as_ref().extension().and_then(|e| e.to_str()),
+        Some("sigmf-meta" | "sigmf-data")
+    )
+}
+
+/// the meta file for either half of a recording, or, for any other capture, the
+/// `{file_name}.sigmf-meta` which describes it in place
+pub fn meta_path(path: impl AsRef<Path>) -> PathBuf {
+    let path = path.as_ref();
+    if is_sigmf(path) {
+        return path.with_extension("sigmf-meta");
+    }
+    let mut meta = path.as_os_str().to_owned();
+    meta.push(".sigmf-meta");
+    PathBuf::from(meta)
+}
+
+/// where we describe a recording which isn't ours, next to its own meta, which we leave alone
+pub fn sidecar_path(path: impl AsRef<Path>) -> PathBuf {
+    let meta = meta_path(path);
+    let stem = meta
+        .file_stem()
+        .expect("meta files have names")
+        .to_string_lossy()
+        .to_string();
+    meta.with_file_name(format!("{stem}.{RECORDER}.sigmf-meta"))
+}
+
+/// a recording, or a capture which has been described in place, e.g. by `perfect-packets`
+pub fn has_meta(path: impl AsRef<Path>) -> bool {
+    is_sigmf(&path) || meta_path(path).is_file()
+}
+
+/// open a recording from either of its files, or a capture described in place
+pub fn open(path: impl AsRef<Path>) -> Result<(Meta, IqReader<io::BufReader<fs::File>>)> {
+    let path = path.as_ref();
+    let meta_path = meta_path(path);
+    let meta = Meta::read(&meta_path)?;
+    let data_path = match &meta.global.dataset {
+        Some(dataset) => meta_path.with_file_name(dataset),
+        None if is_sigmf(path) => meta_path.with_extension("sigmf-data"),
+        None => path.to_path_buf(),
+    };
+    let inp = crate::iq::open(&data_path, Some(meta.format()?))?;
+    Ok((meta, inp))
+}
+
+/// where a burst from `squelch` was in the original capture, in samples, and how strong it was;
+/// its `end` is the chunk after its run, which has had the `SMOOTHING` padding trimmed off both
+/// ends
+pub fn burst_annotation(config: &Config, signal: &Signal) -> Annotation {
+    let end = (signal.end.saturating_sub(SMOOTHING) * CHUNK_BY * config.decimation) as u64;
+    let count = (signal.fm.len() * config.decimation) as u64;
+    Annotation {
+        sample_start: end.saturating_sub(count),
+        sample_count: Some(count),
+        label: None,
+        comment: None,
+        packet_type: None,
+        crc: None,
+        rssi: Some(signal.rssi),
+        snr: Some(signal.snr),
+        extra: Map::new(),
+    }
+}
+
+impl Annotation {
+    /// whether we made this annotation, i.e. it has any of our fields
+    fn is_ours(&self) -> bool {
+        self.packet_type.is_some()
+            || self.crc.is_some()
+            || self.rssi.is_some()
+            || self.snr.is_some()
+    }
+}
+
+#[test]
+fn test_round_trip() {
+    let config = Config {
+        decimation: 16,
+        sample_rate: 3_200_000,
+        deviation: 60_000,
+        shift: 257_000.,
+        filter: None,
+        energy: None,
+    };
+    let mut meta = Meta::for_capture(
+        Format::Cf32,
+        &config,
+        Some("outbound.sr3200000.fc32".into()),
+    )
+    .expect("sigmf format");
+    meta.annotations.push(Annotation {
+        label: Some("172".to_string()),
+        packet_type: Some(172),
+        crc: Some(true),
+        ..burst_annotation(
+            &config,
+            &Signal {
+                end: 1000,
+                fm: vec![0.; 3200],
+                rssi: -20.5,
+                snr: 14.25,
+            },
+        )
+    });
+
+    let json = serde_json::to_string(&meta).expect("serialisable");
+    assert!(json.contains(r#""core:datatype":"cf32_le""#), "{json}");
+    assert!(
+        json.contains(r#""core:sample_start":174080,"core:sample_count":51200"#),
+        "{json}"
+    );
+    assert!(json.contains(r#""shinelink:snr":14.25"#), "{json}");
+
+    let read: Meta = serde_json::from_str(&json).expect("parseable");
+    assert_eq!(read, meta);
+    assert_eq!(read.format().expect("known"), Format::Cf32);
+    let read_config = read.config(&Config {
+        sample_rate: 1,
+        shift: 1.,
+        ..config
+    });
+    assert_eq!(read_config.sample_rate, 3_200_000);
+    assert_eq!(read_config.shift, 257_000.);
+}
+
+#[test]
+fn test_foreign() {
+    // as written by another tool, with fields we don't know about
+    let meta: Meta = serde_json::from_str(
+        r#"{
+            "global": {
+                "core:datatype": "ci16_le",
+                "core:sample_rate": 2400000.0,
+                "core:version": "1.0.0",
+                "core:author": "someone"
+            },
+            "captures": [{"core:sample_start": 0, "core:frequency": 433920000}],
+            "annotations": [{"core:sample_start": 500, "core:label": "theirs", "other:x": 1}]
+        }"#,
+    )
+    .expect("valid sigmf");
+    assert!(!meta.is_ours());
+    assert_eq!(meta.format().expect("known"), Format::Cs16);
+    assert_eq!(meta.captures[0].frequency, Some(433_920_000.));
+    // we can't guess the shift
+    let config = meta.config(&Config {
+        decimation: 16,
+        sample_rate: 2_880_000,
+        deviation: 60_000,
+        shift: 476_000.,
+        filter: None,
+        energy: None,
+    });
+    assert_eq!(config.sample_rate, 2_400_000);
+    assert_eq!(config.shift, 476_000.);
+
+    // annotating it keeps their fields, and their annotations, but replaces ours
+    let ours = |sample_start| Annotation {
+        sample_start,
+        crc: Some(true),
+        ..burst_annotation(
+            &config,
+            &Signal {
+                end: 0,
+                fm: Vec::new(),
+                rssi: -20.,
+                snr: 10.,
+            },
+        )
+    };
+    let mut annotated = meta.clone();
+    annotated.annotate(vec![ours(1000), ours(100)]);
+    annotated.annotate(vec![ours(1000), ours(100)]);
+    let json = serde_json::to_string(&annotated).expect("serialisable");
+    assert!(json.contains(r#""core:author":"someone""#), "{json}");
+    assert!(json.contains(r#""other:x":1"#), "{json}");
+    let read: Meta = serde_json::from_str(&json).expect("parseable");
+    assert_eq!(
+        read.annotations
+            .iter()
+            .map(|a| a.sample_start)
+            .collect::<Vec<_>>(),
+        [100, 500, 1000]
+    );
+}
@@ -0,0 +1,296 @@
+//! slips: where the bits after some point in a packet are early or late, because a run was
+//! misread, e.g. the up-pulse that was 3.5 bits long
+//!
+//! After a slip, every byte decrypts to rubbish, but the same bits, read from a few places
+//! earlier or later, decrypt to plaintext again. Plaintext is mostly nulls and ascii, so we can
+//! see roughly where that happens, then try splicing at each bit near there. The 172's payload
+//! is binary, so only its nulls count.
+
+use crate::bits_to_byte;
+use crate::decode::ENCRYPTION_KEY;
+use crate::frame::PAYLOAD_AT;
+use crate::packets::t172;
+use itertools::Itertools;
+use std::ops::Range;
+
+/// how far (in bits) a slip can move the rest of the packet; a whole byte is a different key phase
+const MAX_SHIFT: isize = 8;
+/// how many more plausible bytes a slip must explain before we believe in it
+const PENALTY: i32 = 3;
+/// we try splicing at every bit from this many bytes before the plaintext stops, to this many
+/// after it reappears
+const SEARCH_BYTES: usize = 2;
+/// the most splices we'll check, across every combination of places; each is another chance
+/// for the checksum to match by accident
+const MAX_TRIALS: usize = 2048;
+
+/// Find where the alignment of `bytes` (a length byte, then the encrypted frame) changes, and
+/// try splicing out up to `max_slips` slips until `check` accepts the result.
+///
+/// Returns what `check` returned, and how many slips were spliced out.
+pub(crate) fn realign<T>(
+    bytes: &[u8],
+    max_slips: usize,
+    check: impl Fn(&[u8]) -> Option<T>,
+) -> Option<(T, usize)> {
+    let bits = bytes
+        .iter()
+        .flat_map(|byte| (0..8).rev().map(move |i| byte >> i & 1 == 1))
+        .collect_vec();
+    let len = usize::from(*bytes.first()?);
+
+    let changes = alignments(&bits, len)
+        .into_iter()
+        .enumerate()
+        .tuple_windows()
+        .filter(|((_, a), (_, b))| a != b)
+        .map(|((_, before), (byte, after))| (byte, before, after))
+        .collect_vec();
+    if changes.is_empty() || changes.len() > max_slips {
+        return None;
+    }
+
+    // every bit near each change, in the frame's (not the damaged stream's) bit numbering; in
+    // a run of bytes which could be either (e.g. binary), the slip could be anywhere in it
+    let places = changes
+        .iter()
+        .map(|&(byte, before, after)| {
+            let stops = (1..byte)
+                .rev()
+                .find(|&b| plausible(&bits, len, b, before))
+                .map_or(byte, |b| b + 1);
+            let starts = (byte..=len)
+                .find(|&b| plausible(&bits, len, b, after))
+                .unwrap_or(byte);
+            (
+                stops.saturating_sub(SEARCH_BYTES) * 8..(starts + SEARCH_BYTES) * 8,
+                after,
+            )
+        })
+        .collect_vec();
+    for splices in in_order(&places, MAX_TRIALS) {
+        let spliced = (0..(1 + len) * 8)
+            .map(|bit| {
+                let shift = splices
+                    .iter()
+                    .take_while(|(at, _)| *at <= bit)
+                    .last()
+                    .map_or(0, |(_, shift)| *shift);
+                let from = bit as isize + shift;
+                usize::try_from(from)
+                    .ok()
+                    .and_then(|from| bits.get(from))
+                    .copied()
+                    .unwrap_or(false)
+            })
+            .collect_vec();
+        let spliced = spliced.chunks_exact(8).map(bits_to_byte).collect_vec();
+        if let Some(found) = check(&spliced) {
+            return Some((found, changes.len()));
+        }
+    }
+
+    None
+}
+
+/// up to `limit` ways of picking a splice from each of `places` (bits, and the shift after
+/// them), each after the last; only those, so the limit bounds the work
+fn in_order(places: &[(Range<usize>, isize)], limit: usize) -> Vec<Vec<(usize, isize)>> {
+    // the latest each can be, and still leave room for the rest
+    let mut latest = vec![0; places.len()];
+    let mut next = usize::MAX;
+    for (i, (bits, _)) in places.iter().enumerate().rev() {
+        let Some(last) = bits.end.checked_sub(1).map(|end| end.min(next)) else {
+            return Vec::new();
+        };
+        if last < bits.start {
+            return Vec::new();
+        }
+        latest[i] = last;
+        next = last.saturating_sub(1);
+    }
+
+    fn pick(
+        places: &[(Range<usize>, isize)],
+        latest: &[usize],
+        from: usize,
+        chosen: &mut Vec<(usize, isize)>,
+        found: &mut Vec<Vec<(usize, isize)>>,
+        limit: usize,
+    ) {
+        let Some(((bits, shift), rest)) = places.split_first() else {
+            found.push(chosen.clone());
+            return;
+        };
+        for bit in bits.start.max(from)..=latest[0] {
+            if found.len() >= limit {
+                return;
+            }
+            chosen.push((bit, *shift));
+            pick(rest, &latest[1..], bit + 1, chosen, found, limit);
+            chosen.pop();
+        }
+    }
+
+    let mut found = Vec::new();
+    pick(places, &latest, 0, &mut Vec::new(), &mut found, limit);
+    found
+}
+
+/// for each byte of the frame (0 being the length byte), how far its bits have moved, by a
+/// Viterbi search over shifts, scored by how plausible each byte's plaintext is
+fn alignments(bits: &[bool], len: usize) -> Vec<isize> {
+    let shifts = (-MAX_SHIFT..=MAX_SHIFT).collect_vec();
+
+    // the length byte is where "jack" said, so everything starts aligned
+    let mut score = shifts
+        .iter()
+        .map(|&shift| if shift == 0 { 0 } else { i32::MIN / 2 })
+        .collect_vec();
+    let mut from = Vec::with_capacity(len);
+    for byte in 1..=len {
+        let prev = score.clone();
+        let best = prev.iter().position_max().expect("non-empty");
+        let mut came = Vec::with_capacity(shifts.len());
+        for (s, &shift) in shifts.iter().enumerate() {
+            let (origin, before) = if prev[best] - PENALTY > prev[s] {
+                (best, prev[best] - PENALTY)
+            } else {
+                (s, prev[s])
+            };
+            score[s] = before + i32::from(plausible(bits, len, byte, shift));
+            came.push(origin);
+        }
+        from.push(came);
+    }
+
+    let mut s = score.iter().position_max().expect("non-empty");
+    let mut path = vec![0; len + 1];
+    for byte in (1..=len).rev() {
+        path[byte] = shifts[s];
+        s = from[byte - 1][s];
+    }
+    path
+}
+
+/// whether frame byte `byte` (0 being the length byte), read `shift` bits along, decrypts to
+/// something that looks like plaintext
+fn plausible(bits: &[bool], len: usize, byte: usize, shift: isize) -> bool {
+    let start = (byte * 8) as isize + shift;
+    let Some(bits) = usize::try_from(start)
+        .ok()
+        .and_then(|start| bits.get(start..start + 8))
+    else {
+        return false;
+    };
+    let plain = bits_to_byte(bits) ^ ENCRYPTION_KEY[(byte - 1) % ENCRYPTION_KEY.len()];
+    // after the length byte and the header, a 172 is binary
+    if len == PAYLOAD_AT + t172::LEN + 2 && byte > PAYLOAD_AT {
+        return plain == 0;
+    }
+    plain == 0 || plain.is_ascii_uppercase() || plain.is_ascii_digit()
+}
+
+#[cfg(test)]
+fn test_frame() -> (Vec<u8>, Vec<bool>) {
+    use crate::encode::encode;
+    use crate::frame::Frame;
+
+    // a 172-ish payload, with lots of nulls
+    let mut payload = vec![0u8; 60];
+    payload[10..20].copy_from_slice(b"0123456789");
+    payload[40] = 0xe7;
+    let frame = Frame {
+        seq: b'9',
+        version: [1, 0, 0x10],
+        lanbox_serial: "KWK1CGQ11A".to_string(),
+        link_serial: "HZL0CGQ11A".to_string(),
+        unknown: 3,
+        packet_type: payload.len() as u16,
+        payload,
+    };
+    let bits = encode(&frame)[5 + 4..]
+        .iter()
+        .flat_map(|byte| (0..8).rev().map(move |i| byte >> i & 1 == 1))
+        .collect_vec();
+    (frame.to_plain_bytes(), bits)
+}
+
+#[test]
+fn test_realign() {
+    use crate::decode::decrypt_aligned;
+
+    let (frame, mut bits) = test_frame();
+    // a bit lost in the payload, another gained later on, and some mess after the end
+    bits.remove(8 * 40 + 3);
+    bits.insert(8 * 70, true);
+    bits.extend([false, true, false, true]);
+    let bytes = bits.chunks_exact(8).map(bits_to_byte).collect_vec();
+    assert_eq!(decrypt_aligned(&bytes), None);
+
+    assert_eq!(realign(&bytes, 2, decrypt_aligned), Some((frame, 2)));
+    assert_eq!(realign(&bytes, 1, decrypt_aligned), None);
+}
+
+#[test]
+fn test_realign_byte() {
+    use crate::decode::decrypt_aligned;
+
+    // a whole byte gained, which looks like a change of key phase
+    let (frame, mut bits) = test_frame();
+    for _ in 0..8 {
+        bits.insert(8 * 50, false);
+    }
+    let bytes = bits.chunks_exact(8).map(bits_to_byte).collect_vec();
+    assert_eq!(realign(&bytes, 1, decrypt_aligned), Some((frame, 1)));
+}
+
+#[test]
+fn test_in_order() {
+    let places = [(0..3, 1), (1..4, -1), (3..5, 2)];
+    let all = in_order(&places, usize::MAX);
+    assert_eq!(all.len(), 9);
+    assert!(all.iter().all(|splices| {
+        splices.len() == 3 && splices.iter().tuple_windows().all(|(a, b)| a.0 < b.0)
+    }));
+    assert_eq!(all[0], [(0, 1), (1, -1), (3, 2)]);
+    assert_eq!(in_order(&places, 2).len(), 2);
+
+    // nowhere for the second to go
+    assert!(in_order(&[(5..9, 1), (0..5, -1)], usize::MAX).is_empty());
+}
+
+#[test]
+fn test_realign_telemetry() {
+    use crate::decode::decrypt_aligned;
+    use crate::encode::encode;
+    use crate::frame::Frame;
+
+    // a real 172, which is binary, not text, with a bit lost part way through
+    let payload = include_bytes!("../172/2025-07-17T22_43_19.17023.pkt").to_vec();
+    let frame = Frame {
+        seq: b'9',
+        version: [1, 0, 0x10],
+        lanbox_serial: "KWK1CGQ11A".to_string(),
+        link_serial: "HZL0CGQ11A".to_string(),
+        unknown: 3,
+        packet_type: payload.len() as u16,
+        payload,
+    };
+    let mut bits = encode(&frame)[5 + 4..]
+        .iter()
+        .flat_map(|byte| (0..8).rev().map(move |i| byte >> i & 1 == 1))
+        .collect_vec();
+    // from a run, as a misread run length loses them; and whatever came after the transmission
+    let lost = (8 * 120..)
+        .find(|&i| bits[i] == bits[i + 1])
+        .expect("a run");
+    bits.remove(lost);
+    bits.extend([true, false, true, false]);
+    let bytes = bits.chunks_exact(8).map(bits_to_byte).collect_vec();
+    assert_eq!(decrypt_aligned(&bytes), None);
+    assert_eq!(
+        realign(&bytes, 1, decrypt_aligned),
+        Some((frame.to_plain_bytes(), 1))
+    );
+}
@@ -0,0 +1,254 @@
+//! places complex samples come from: files, radios, ...
+
+use crate::iq::{Format, IqReader};
+use crate::u8_to_f32;
+use anyhow::{Result, anyhow, ensure};
+use num_complex::Complex32;
+use std::io;
+use std::io::{Read, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+
+pub trait SampleSource {
+    /// fill (some of) `buf`, returning how many samples were written; zero at the end of the stream
+    fn read_samples(&mut self, buf: &mut [Complex32]) -> Result<usize>;
+}
+
+impl<S: SampleSource + ?Sized> SampleSource for Box<S> {
+    fn read_samples(&mut self, buf: &mut [Complex32]) -> Result<usize> {
+        (**self).read_samples(buf)
+    }
+}
+
+impl SampleSource for &[Complex32] {
+    fn read_samples(&mut self, buf: &mut [Complex32]) -> Result<usize> {
+        let len = buf.len().min(self.len());
+        let (head, tail) = self.split_at(len);
+        buf[..len].copy_from_slice(head);
+        *self = tail;
+        Ok(len)
+    }
+}
+
+/// a local rtl-sdr dongle
+pub struct RtlSdr {
+    sdr: rtlsdr_rs::RtlSdr,
+    bytes: Vec<u8>,
+}
+
+impl RtlSdr {
+    /// `gain` is in tenths of a dB, or automatic if `None`
+    pub fn open(
+        device_index: usize,
+        center_freq: u32,
+        sample_rate: u32,
+        gain: Option<i32>,
+    ) -> Result<RtlSdr> {
+        let mut sdr = rtlsdr_rs::RtlSdr::open(device_index).map_err(rtl_err)?;
+        sdr.set_tuner_gain(match gain {
+            Some(gain) => rtlsdr_rs::TunerGain::Manual(gain),
+            None => rtlsdr_rs::TunerGain::Auto,
+        })
+        .map_err(rtl_err)?;
+        sdr.set_bias_tee(false).map_err(rtl_err)?;
+        sdr.reset_buffer().map_err(rtl_err)?;
+        sdr.set_center_freq(center_freq).map_err(rtl_err)?;
+        sdr.set_sample_rate(sample_rate).map_err(rtl_err)?;
+
+        Ok(RtlSdr {
+            sdr,
+            bytes: Vec::new(),
+        })
+    }
+}
+
+impl SampleSource for RtlSdr {
+    fn read_samples(&mut self, buf: &mut [Complex32]) -> Result<usize> {
+        // the dongle insists on multiples of 512 bytes
+        let len = (buf.len() * 2) / 512 * 512;
+        assert!(len > 0, "buffer must be at least 256 samples");
+        self.bytes.resize(len, 0);
+
+        let len = self.sdr.read_sync(&mut self.bytes).map_err(rtl_err)?;
+        for (out, iq) in buf.iter_mut().zip(self.bytes[..len].chunks_exact(2)) {
+            *out = Complex32::new(u8_to_f32(iq[0]), u8_to_f32(iq[1]));
+        }
+        Ok(len / 2)
+    }
+}
+
+impl Drop for RtlSdr {
+    fn drop(&mut self) {
+        if let Err(e) = self.sdr.close() {
+            log::warn!("closing rtl-sdr: {e:?}");
+        }
+    }
+}
+
+/// what an rtl_tcp server says about its dongle when we connect
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DongleInfo {
+    pub tuner_type: u32,
+    pub tuner_gain_count: u32,
+}
+
+/// a dongle on another machine, via rtl_tcp
+pub struct RtlTcp {
+    commands: TcpStream,
+    samples: IqReader<io::BufReader<TcpStream>>,
+    pub info: DongleInfo,
+}
+
+impl RtlTcp {
+    pub fn connect(addr: impl ToSocketAddrs) -> Result<RtlTcp> {
+        let stream = TcpStream::connect(addr)?;
+        stream.set_nodelay(true)?;
+
+        let mut header = [0u8; 12];
+        (&stream).read_exact(&mut header)?;
+        ensure!(
+            header.starts_with(b"RTL0"),
+            "not an rtl_tcp server: {header:02x?}"
+        );
+        let info = DongleInfo {
+            tuner_type: u32::from_be_bytes(header[4..8].try_into().expect("fixed size")),
+            tuner_gain_count: u32::from_be_bytes(header[8..12].try_into().expect("fixed size")),
+        };
+
+        Ok(RtlTcp {
+            commands: stream.try_clone()?,
+            samples: IqReader::new(
+                io::BufReader::with_capacity(256 * 1024, stream),
+                Format::Cu8,
+            )?,
+            info,
+        })
+    }
+
+    pub fn set_center_freq(&mut self, freq: u32) -> Result<()> {
+        self.command(0x01, freq)
+    }
+
+    pub fn set_sample_rate(&mut self, sample_rate: u32) -> Result<()> {
+        self.command(0x02, sample_rate)
+    }
+
+    /// `gain` is in tenths of a dB, or automatic if `None`
+    pub fn set_gain(&mut self, gain: Option<i32>) -> Result<()> {
+        match gain {
+            Some(gain) => {
+                self.command(0x03, 1)?;
+                self.command(0x04, gain as u32)
+            }
+            None => self.command(0x03, 0),
+        }
+    }
+
+    pub fn set_freq_correction(&mut self, ppm: i32) -> Result<()> {
+        self.command(0x05, ppm as u32)
+    }
+
+    fn command(&mut self, command: u8, param: u32) -> Result<()> {
+        let mut buf = [command, 0, 0, 0, 0];
+        buf[1..].copy_from_slice(&param.to_be_bytes());
+        self.commands.write_all(&buf)?;
+        Ok(())
+    }
+}
+
+impl SampleSource for RtlTcp {
+    fn read_samples(&mut self, buf: &mut [Complex32]) -> Result<usize> {
+        self.samples.read_samples(buf)
+    }
+}
+
+fn rtl_err(e: impl std::fmt::Debug) -> anyhow::Error {
+    anyhow!("rtl-sdr: {e:?}")
+}
+
+/// a fake rtl_tcp server, which records the commands it's sent and replays a cu8 file,
+/// once the client has sent `commands` commands
+#[cfg(test)]
+pub(crate) fn fake_rtl_tcp(
+    path: std::path::PathBuf,
+    commands: usize,
+) -> (std::net::SocketAddr, std::thread::JoinHandle<Vec<[u8; 5]>>) {
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").expect("local listen");
+    let addr = listener.local_addr().expect("bound");
+    let server = std::thread::spawn(move || {
+        let (mut client, _) = listener.accept().expect("client connects");
+        // RTL0, R820T, 29 gains
+        client
+            .write_all(b"RTL0\0\0\0\x05\0\0\0\x1d")
+            .expect("header");
+
+        let mut seen = Vec::with_capacity(commands);
+        for _ in 0..commands {
+            let mut command = [0u8; 5];
+            client.read_exact(&mut command).expect("command");
+            seen.push(command);
+        }
+
+        io::copy(
+            &mut std::fs::File::open(&path).expect("capture"),
+            &mut client,
+        )
+        .expect("stream");
+        seen
+    });
+    (addr, server)
+}
+
+#[test]
+fn test_rtl_tcp() {
+    use crate::listen::{listen, one_chat_outbound, quiet_noise};
+    use crate::squelch::Config;
+    use std::sync::atomic::AtomicBool;
+
+    let mut samples = quiet_noise(640_000, 3);
+    samples.extend(one_chat_outbound());
+    samples.extend(quiet_noise(640_000, 4));
+    let cu8 = crate::modulate::to_cu8(&samples);
+    let path = std::env::temp_dir().join(format!("shinelink-rtl-tcp-{}.cu8", std::process::id()));
+    std::fs::write(&path, cu8).expect("temp file");
+
+    let (addr, server) = fake_rtl_tcp(path.clone(), 4);
+
+    let mut source = RtlTcp::connect(addr).expect("connect");
+    assert_eq!(
+        source.info,
+        DongleInfo {
+            tuner_type: 5,
+            tuner_gain_count: 29
+        }
+    );
+    source.set_sample_rate(3_200_000).expect("command");
+    source.set_center_freq(434_200_000).expect("command");
+    source.set_gain(Some(-10)).expect("command");
+
+    let config = Config {
+        decimation: 16,
+        sample_rate: 3_200_000,
+        deviation: 60_000,
+        shift: 257_000.,
+        filter: None,
+        energy: None,
+    };
+    let mut seen = Vec::new();
+    listen(&mut source, &config, &AtomicBool::new(false), |_, frame| {
+        seen.push(frame)
+    })
+    .expect("stream");
+    std::fs::remove_file(&path).expect("temp file");
+
+    assert_eq!(
+        server.join().expect("server"),
+        [
+            [0x02, 0x00, 0x30, 0xd4, 0x00],
+            [0x01, 0x19, 0xe1, 0x5d, 0xc0],
+            [0x03, 0, 0, 0, 1],
+            [0x04, 0xff, 0xff, 0xff, 0xf6],
+        ]
+    );
+    assert_eq!(seen.len(), 1, "{seen:?}");
+    assert_eq!(seen[0].link_serial, "HZL0CGQ11A");
+}
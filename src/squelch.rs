@@ -1,11 +1,17 @@
+use crate::decode::KNOWN_HEADER_BYTES;
 use crate::demod_fm::FmDemod;
-use crate::read_one_complex_f32;
+use crate::dsp::{Decimator, Nco, lowpass};
+use crate::encode::PREAMBLE;
+use crate::iq::{Format, IqReader};
+use crate::source::SampleSource;
 use anyhow::{Result, ensure};
 use itertools::Itertools;
 use num_complex::Complex32;
-use std::f32::consts::TAU;
+use std::collections::VecDeque;
 use std::io::Read;
+use std::str::FromStr;
 
+#[derive(Debug, Clone)]
 pub struct Config {
     /// how much to lowpass the signal
     pub decimation: usize,
@@ -15,23 +21,377 @@ pub struct Config {
     pub deviation: u32,
     /// where the signal is, in the file (Hz)
     pub shift: f64,
+    /// lowpass the IQ before demodulating, or just average the demodulated samples if `None`
+    pub filter: Option<ChannelFilter>,
+    /// squelch on the power in the channel, or on whether the demodulated samples look clean
+    /// (`is_perfect`) if `None`
+    pub energy: Option<EnergySquelch>,
 }
 
+/// a lowpass on the IQ, so only our channel reaches the discriminator, which also does some of
+/// the decimation
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ChannelFilter {
+    /// of the IQ, by the filter; `Config::decimation` must be a multiple of this
+    pub decimation: usize,
+    pub taps: usize,
+}
+
+impl Default for ChannelFilter {
+    fn default() -> ChannelFilter {
+        ChannelFilter {
+            decimation: 4,
+            taps: 127,
+        }
+    }
+}
+
+/// open the squelch when the power in the channel (after the shift, and any filter, then
+/// averaged over each observation) is well above the noise floor, which follows the chunks
+/// that aren't signal
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct EnergySquelch {
+    /// how far over the noise floor a chunk must be to open the squelch (dB)
+    pub open: f32,
+    /// how far over it the squelch stays open, once open (dB); it then stays open for another
+    /// `SMOOTHING` chunks anyway
+    pub close: f32,
+}
+
+impl Default for EnergySquelch {
+    fn default() -> EnergySquelch {
+        EnergySquelch {
+            open: 10.,
+            close: 6.,
+        }
+    }
+}
+
+impl FromStr for EnergySquelch {
+    type Err = anyhow::Error;
+
+    /// `open,close`, e.g. `10,6`; any left off are the defaults
+    fn from_str(s: &str) -> Result<EnergySquelch> {
+        let mut parts = s.split(',').map(str::trim);
+        let mut energy = EnergySquelch::default();
+        if let Some(open) = parts.next().filter(|v| !v.is_empty()) {
+            energy.open = open.parse()?;
+        }
+        if let Some(close) = parts.next() {
+            energy.close = close.parse()?;
+        }
+        ensure!(
+            parts.next().is_none(),
+            "expected open,close, e.g. 10,6, not {s:?}"
+        );
+        ensure!(
+            energy.close <= energy.open,
+            "the squelch must close below where it opens"
+        );
+        Ok(energy)
+    }
+}
+
+/// a burst, found by the squelch
+#[derive(Debug, Clone, PartialEq)]
+pub struct Signal {
+    /// the chunk (of `CHUNK_BY` observations) after it ended
+    pub end: usize,
+    /// normalised, demodulated, decimated observations
+    pub fm: Vec<f32>,
+    /// the mean power in the channel, over the chunks which opened the squelch (dBFS)
+    pub rssi: f32,
+    /// `rssi` over the noise floor (dB)
+    pub snr: f32,
+}
+
+/// the decoder assumes 100us bits
+const BIT_RATE: u32 = 10_000;
+/// how fast the noise floor follows the power down, and up, per chunk which isn't signal
+const FLOOR_FALL: f32 = 0.1;
+const FLOOR_RISE: f32 = 0.01;
+
+/// how many observations are considered together when deciding if there's a signal
+pub const CHUNK_BY: usize = 16;
+/// how many chunks either side of a signal are included with it
+pub const SMOOTHING: usize = 120;
+/// the longest a frame can be on air: the preamble, "jack", and a length byte of 255
+const MAX_FRAME_BITS: usize = 8 * (PREAMBLE.len() + KNOWN_HEADER_BYTES.len() + 1 + 255);
+/// a run longer than this many of the longest frames isn't a burst, e.g. a carrier holding the
+/// energy squelch open, so is cut off and emitted, rather than growing forever
+const MAX_RUN_FRAMES: usize = 4;
+
 /// reads cu8 samples, and extracts normalised, demodulated, decimated observations
-pub fn squelch(inp: &mut impl Read, config: &Config) -> Result<Vec<(usize, Vec<f32>)>> {
-    ensure!(
-        config.deviation <= config.sample_rate / 2,
-        "deviation must be less than half the sample rate"
-    );
-    ensure!(
-        config.shift.abs() <= config.sample_rate as f64 / 2.,
-        "shift must be less than half the sample rate"
-    );
+pub fn squelch(inp: &mut impl Read, config: &Config) -> Result<Vec<Signal>> {
+    squelch_source(&mut IqReader::new(inp, Format::Cu8)?, config)
+}
+
+/// as `squelch`, but from any source
+pub fn squelch_source(source: &mut impl SampleSource, config: &Config) -> Result<Vec<Signal>> {
+    let mut squelch = Squelch::new(config)?;
+    let mut merged = Vec::with_capacity(8);
+
+    let mut samples = vec![Complex32::new(0., 0.); 64 * 1024];
+    loop {
+        let len = source.read_samples(&mut samples)?;
+        if len == 0 {
+            break;
+        }
+        merged.extend(squelch.push(&samples[..len]));
+    }
 
-    let observations = read_shift_demod_decimate(inp, config)?;
+    merged.extend(squelch.finish());
+    Ok(merged)
+}
+
+/// `squelch`, a block of samples at a time, emitting each signal as soon as it's over
+///
+/// Memory is bounded by the longest run (`MAX_RUN_FRAMES`), plus `SMOOTHING` chunks of
+/// look-ahead.
+pub struct Squelch {
+    channel: Option<Decimator>,
+    demod: FmDemod,
+    /// of the demodulated samples, after any `channel` decimation
+    decimation: usize,
+    nco: Nco,
+    /// demodulated samples, waiting to be decimated
+    buf: Vec<f32>,
+    /// the sum of the IQ of the samples in `buf`
+    iq: Complex32,
+    /// observations, waiting to make up a chunk
+    chunk: Vec<f32>,
+    /// the sum of the power of the observations in `chunk`
+    chunk_power: f32,
+    /// how many chunks we've seen
+    chunks: usize,
+    /// whether each of the last `2 * SMOOTHING` chunks looked like signal
+    perfects: VecDeque<bool>,
+    perfect_count: usize,
+    /// chunks we've seen, but can't decide about until we've seen `SMOOTHING` more, with their
+    /// power (dBFS), and whether they looked like signal
+    pending: VecDeque<(Vec<f32>, f32, bool)>,
+    /// the signal we're in the middle of, if any
+    run: Vec<f32>,
+    /// observations, after which `run` is emitted, even if it hasn't ended
+    max_run: usize,
+    /// the power (linear) of the chunks of `run` which looked like signal, and how many
+    run_power: (f32, usize),
+    energy: Option<EnergySquelch>,
+    /// of the chunks which didn't look like signal (dBFS)
+    floor: Option<f32>,
+    /// whether the energy squelch is open
+    open: bool,
+}
+
+impl Squelch {
+    pub fn new(config: &Config) -> Result<Squelch> {
+        ensure!(
+            config.deviation <= config.sample_rate / 2,
+            "deviation must be less than half the sample rate"
+        );
+        ensure!(
+            config.shift.abs() <= config.sample_rate as f64 / 2.,
+            "shift must be less than half the sample rate"
+        );
+
+        let (channel, decimation, demod_rate) = match config.filter {
+            Some(filter) => {
+                ensure!(
+                    filter.decimation > 0 && config.decimation.is_multiple_of(filter.decimation),
+                    "the decimation must be a multiple of the filter's"
+                );
+                // Carson's rule, for half the bandwidth
+                let cutoff = f64::from(config.deviation + BIT_RATE);
+                let rate = config.sample_rate / filter.decimation as u32;
+                ensure!(
+                    cutoff < f64::from(rate) / 2.,
+                    "the filter's decimation is too high for the deviation"
+                );
+                let taps = lowpass(cutoff, f64::from(config.sample_rate), filter.taps);
+                (
+                    Some(Decimator::new(&taps, filter.decimation)),
+                    config.decimation / filter.decimation,
+                    rate,
+                )
+            }
+            None => (None, config.decimation, config.sample_rate),
+        };
+
+        let observations_per_bit =
+            config.sample_rate as usize / config.decimation / BIT_RATE as usize;
+        let max_run = MAX_RUN_FRAMES * MAX_FRAME_BITS * observations_per_bit.max(1)
+            + 2 * SMOOTHING * CHUNK_BY;
+
+        Ok(Squelch {
+            channel,
+            demod: FmDemod::new(config.deviation, demod_rate),
+            decimation,
+            nco: Nco::new(config.shift, config.sample_rate),
+            buf: Vec::with_capacity(decimation),
+            iq: Complex32::new(0., 0.),
+            chunk: Vec::with_capacity(CHUNK_BY),
+            chunk_power: 0.,
+            chunks: 0,
+            perfects: VecDeque::with_capacity(2 * SMOOTHING + 1),
+            perfect_count: 0,
+            pending: VecDeque::with_capacity(SMOOTHING + 1),
+            run: Vec::with_capacity(64 * CHUNK_BY),
+            max_run,
+            run_power: (0., 0),
+            energy: config.energy,
+            floor: None,
+            open: false,
+        })
+    }
+
+    /// the signals which ended somewhere in (or before) these samples
+    pub fn push(&mut self, samples: &[Complex32]) -> Vec<Signal> {
+        let mut picked = Vec::new();
+        for &sample in samples {
+            let Some((observation, power)) = self.shift_demod_decimate(sample) else {
+                continue;
+            };
+            self.chunk.push(observation);
+            self.chunk_power += power;
+            if self.chunk.len() == CHUNK_BY {
+                let chunk = std::mem::replace(&mut self.chunk, Vec::with_capacity(CHUNK_BY));
+                picked.extend(self.push_chunk(chunk));
+            }
+        }
+        picked
+    }
+
+    /// the end of the stream; the last `SMOOTHING` chunks are never considered signal
+    pub fn finish(mut self) -> Option<Signal> {
+        if !self.chunk.is_empty() {
+            let chunk = std::mem::take(&mut self.chunk);
+            if let Some(picked) = self.push_chunk(chunk) {
+                return Some(picked);
+            }
+        }
 
-    let chunk_by = 16;
-    let smoothing = 120;
+        if self.run.is_empty() {
+            return None;
+        }
+        Some(self.take_run(self.chunks - SMOOTHING))
+    }
+
+    /// the demodulated observation, and the power of the IQ it came from
+    fn shift_demod_decimate(&mut self, sample: Complex32) -> Option<(f32, f32)> {
+        let mut sample = self.nco.mix(sample);
+        if let Some(channel) = &mut self.channel {
+            sample = channel.push(sample)?;
+        }
+        self.buf.push(self.demod.update(sample));
+        self.iq += sample;
+
+        if self.buf.len() < self.decimation {
+            return None;
+        }
+        // buf.sort_unstable_by(|a, b| f32::total_cmp(a, b));
+        // let median = buf[buf.len() / 2];
+        let mean = self.buf.iter().sum::<f32>() / self.buf.len() as f32;
+        // averaging the IQ is a crude lowpass, to about the channel
+        let power = (self.iq / self.buf.len() as f32).norm_sqr();
+        self.buf.truncate(0);
+        self.iq = Complex32::new(0., 0.);
+        Some((mean, power))
+    }
+
+    /// a chunk is signal if any chunk within `SMOOTHING` of it looks like signal, so we can only
+    /// decide about the chunk `SMOOTHING` before this one
+    fn push_chunk(&mut self, chunk: Vec<f32>) -> Option<Signal> {
+        let mut picked = None;
+        if self.chunks >= SMOOTHING {
+            let chunk_no = self.chunks - SMOOTHING;
+            let (decided, level, looked) = self.pending.pop_front().expect("pending chunks");
+            let is_signal = chunk_no > SMOOTHING && self.perfect_count > 0;
+
+            if is_signal {
+                self.run.extend_from_slice(&decided);
+                if looked {
+                    self.run_power.0 += 10f32.powf(level / 10.);
+                    self.run_power.1 += 1;
+                }
+                if self.run.len() >= self.max_run {
+                    picked = Some(self.take_run(chunk_no + 1));
+                }
+            } else if !self.run.is_empty() {
+                picked = Some(self.take_run(chunk_no));
+            }
+        }
+
+        let level = 10. * (self.chunk_power / chunk.len() as f32).max(1e-12).log10();
+        self.chunk_power = 0.;
+        let perfect = match self.energy {
+            Some(energy) => self.is_loud(&energy, level),
+            None => is_perfect(&chunk),
+        };
+        let floor = *self.floor.get_or_insert(level);
+        if !perfect {
+            let rate = if level < floor {
+                FLOOR_FALL
+            } else {
+                FLOOR_RISE
+            };
+            self.floor = Some(floor + (level - floor) * rate);
+        }
+        self.perfects.push_back(perfect);
+        self.perfect_count += usize::from(perfect);
+        if self.perfects.len() > 2 * SMOOTHING {
+            let expired = self.perfects.pop_front().expect("non-empty");
+            self.perfect_count -= usize::from(expired);
+        }
+
+        self.pending.push_back((chunk, level, perfect));
+        self.chunks += 1;
+        picked
+    }
+
+    /// whether a chunk with this `level` (dBFS) opens, or keeps open, the energy squelch
+    fn is_loud(&mut self, energy: &EnergySquelch, level: f32) -> bool {
+        let Some(floor) = self.floor else {
+            return false;
+        };
+        let over = level - floor;
+        self.open = over >= energy.open || (self.open && over >= energy.close);
+        self.open
+    }
+
+    fn take_run(&mut self, end: usize) -> Signal {
+        let (power, chunks) = std::mem::take(&mut self.run_power);
+        let rssi = 10. * (power / chunks.max(1) as f32).max(1e-12).log10();
+        let signal = Signal {
+            end,
+            fm: trim_normalise(&self.run),
+            rssi,
+            snr: rssi - self.floor.unwrap_or(rssi),
+        };
+        self.run.clear();
+        signal
+    }
+}
+
+/// the run includes `SMOOTHING` chunks either side of the signal, which aren't included in the normalisation
+fn trim_normalise(run: &[f32]) -> Vec<f32> {
+    let smoothing = SMOOTHING * CHUNK_BY;
+    if run.len() > smoothing * 2 {
+        normalise(&run[smoothing..run.len() - smoothing])
+    } else {
+        run.to_vec()
+    }
+}
+
+/// the original, whole-buffer, implementation, which `Squelch` must match
+#[cfg(test)]
+fn squelch_batch(
+    source: &mut impl SampleSource,
+    config: &Config,
+) -> Result<Vec<(usize, Vec<f32>)>> {
+    let observations = read_shift_demod_decimate(source, config)?;
+
+    let chunk_by = CHUNK_BY;
+    let smoothing = SMOOTHING;
 
     let perfects = observations.chunks(chunk_by).map(is_perfect).collect_vec();
     let smoothed = smooth(&perfects, smoothing);
@@ -40,6 +400,7 @@ pub fn squelch(inp: &mut impl Read, config: &Config) -> Result<Vec<(usize, Vec<f
     Ok(merged)
 }
 
+#[cfg(test)]
 fn merge_runs(
     observations: &[f32],
     smoothed: &[bool],
@@ -77,31 +438,36 @@ fn merge_runs(
     picked
 }
 
-fn read_shift_demod_decimate(inp: &mut impl Read, config: &Config) -> Result<Vec<f32>> {
+#[cfg(test)]
+fn read_shift_demod_decimate(source: &mut impl SampleSource, config: &Config) -> Result<Vec<f32>> {
     let mut demod = FmDemod::new(config.deviation, config.sample_rate);
 
     let mut buf = Vec::with_capacity(64);
     let mut observations = Vec::new();
 
-    let shift_rate = f64::from(TAU) * config.shift / config.sample_rate as f64;
-    let mut i = 0f64;
+    let mut nco = Nco::new(config.shift, config.sample_rate);
+
+    let mut samples = vec![Complex32::new(0., 0.); 64 * 1024];
+    loop {
+        let len = source.read_samples(&mut samples)?;
+        if len == 0 {
+            break;
+        }
 
-    while let Some(mut sample) = read_one_complex_f32(inp)? {
-        i += 1.;
-        sample *= Complex32::new((shift_rate * i).cos() as f32, (shift_rate * i).sin() as f32);
-        buf.push(demod.update(sample));
+        for &sample in &samples[..len] {
+            buf.push(demod.update(nco.mix(sample)));
 
-        if buf.len() == config.decimation {
-            // buf.sort_unstable_by(|a, b| f32::total_cmp(a, b));
-            // let median = buf[buf.len() / 2];
-            let mean = buf.iter().sum::<f32>() / buf.len() as f32;
-            observations.push(mean);
-            buf.truncate(0);
+            if buf.len() == config.decimation {
+                let mean = buf.iter().sum::<f32>() / buf.len() as f32;
+                observations.push(mean);
+                buf.truncate(0);
+            }
         }
     }
     Ok(observations)
 }
 
+#[cfg(test)]
 fn smooth(orig: &[bool], s: usize) -> Vec<bool> {
     let end = orig.len() - s;
     (0..orig.len())
@@ -176,3 +542,179 @@ fn is_perfect(chunk: &[f32]) -> bool {
 
     max - min < 2.
 }
+
+#[test]
+fn test_streaming_matches_batch() {
+    use crate::listen::{one_chat_outbound, quiet_noise};
+
+    let config = Config {
+        decimation: 16,
+        sample_rate: 3_200_000,
+        deviation: 60_000,
+        shift: 257_000.,
+        filter: None,
+        energy: None,
+    };
+
+    // two packets, the second still going when the smoothing hits the end, which isn't a whole chunk
+    let mut samples = quiet_noise(300_000, 5);
+    samples.extend(one_chat_outbound());
+    samples.extend(quiet_noise(200_000, 6));
+    samples.extend(one_chat_outbound());
+    samples.extend(quiet_noise(20_003, 7));
+
+    let batch = squelch_batch(&mut samples.as_slice(), &config).expect("in-memory");
+    assert_eq!(batch.len(), 2);
+
+    // which didn't measure the power
+    let unmeasured = |signals: Vec<Signal>| {
+        signals
+            .into_iter()
+            .map(|signal| (signal.end, signal.fm))
+            .collect_vec()
+    };
+    assert_eq!(
+        unmeasured(squelch_source(&mut samples.as_slice(), &config).expect("in-memory")),
+        batch
+    );
+
+    // odd block sizes, which split chunks and decimation
+    let mut squelch = Squelch::new(&config).expect("valid config");
+    let mut streamed = Vec::new();
+    for block in samples.chunks(1_237) {
+        streamed.extend(squelch.push(block));
+    }
+    streamed.extend(squelch.finish());
+    assert_eq!(unmeasured(streamed), batch);
+}
+
+#[test]
+fn test_channel_filter() {
+    use crate::decode::{self, decode_with, synthesise};
+    use crate::encode::encode;
+    use crate::frame::Frame;
+    use crate::modulate;
+
+    let frame = Frame::parse(b"RF9\x01\x00\x10KWK1CGQ11AHZL0CGQ11A\x03\x00\x04\x00\x01\x02\x03")
+        .expect("valid frame");
+    let modulation = modulate::Config::default();
+    let mut samples = synthesise(&encode(&frame), &modulation);
+
+    // a louder neighbour, 250kHz away, which is on for longer than we are
+    let step = std::f32::consts::TAU * 250_000. / modulation.sample_rate as f32;
+    for (i, v) in samples.iter_mut().enumerate() {
+        *v += Complex32::from_polar(0.8, step * (i % 2_880_000) as f32);
+    }
+
+    let decoded = |filter| {
+        let config = Config {
+            decimation: 16,
+            sample_rate: modulation.sample_rate,
+            deviation: 60_000,
+            shift: 0.,
+            filter,
+            energy: None,
+        };
+        squelch_source(&mut samples.as_slice(), &config)
+            .expect("in-memory")
+            .iter()
+            .flat_map(|signal| decode_with(&signal.fm, 18., &decode::Config::default()).matches_crc)
+            .filter_map(|(v, _)| Frame::parse(&v).ok())
+            .collect_vec()
+    };
+
+    assert_eq!(decoded(None), vec![]);
+    assert_eq!(decoded(Some(ChannelFilter::default())), vec![frame]);
+}
+
+#[test]
+fn test_energy_squelch() {
+    use crate::decode::synthesise;
+    use crate::encode::encode;
+    use crate::frame::Frame;
+    use crate::modulate;
+
+    let frame = Frame::parse(b"RF9\x01\x00\x10KWK1CGQ11AHZL0CGQ11A\x03\x00\x04\x00\x01\x02\x03")
+        .expect("valid frame");
+    let squelch = |noise, energy| {
+        let modulation = modulate::Config {
+            noise,
+            bt: Some(0.5),
+            ..modulate::Config::default()
+        };
+        let config = Config {
+            decimation: 16,
+            sample_rate: modulation.sample_rate,
+            deviation: 60_000,
+            shift: 0.,
+            filter: None,
+            energy,
+        };
+        let samples = synthesise(&encode(&frame), &modulation);
+        squelch_source(&mut samples.as_slice(), &config).expect("in-memory")
+    };
+    let energy = Some(EnergySquelch::default());
+
+    // the whole burst, about 6600 observations, at -6dBFS
+    let clean = squelch(0.01, energy);
+    assert_eq!(clean.len(), 1);
+    assert!(
+        clean[0].fm.len().abs_diff(6600) < 200,
+        "{}",
+        clean[0].fm.len()
+    );
+    assert!((clean[0].rssi + 6.3).abs() < 0.5, "{}", clean[0].rssi);
+    assert!(clean[0].snr > 40., "{}", clean[0].snr);
+
+    // in noise where the demodulated signal doesn't look clean for long enough
+    let noisy = squelch(0.4, energy);
+    assert_eq!(noisy.len(), 1);
+    assert!(
+        noisy[0].fm.len().abs_diff(6600) < 200,
+        "{}",
+        noisy[0].fm.len()
+    );
+    assert!((10. ..15.).contains(&noisy[0].snr), "{}", noisy[0].snr);
+    assert!(
+        squelch(0.4, None)
+            .iter()
+            .all(|signal| signal.fm.len() < 6000)
+    );
+
+    assert_eq!(
+        "12,8".parse::<EnergySquelch>().expect("valid"),
+        EnergySquelch {
+            open: 12.,
+            close: 8.,
+        }
+    );
+    assert!("6,12".parse::<EnergySquelch>().is_err());
+}
+
+#[test]
+fn test_run_cap() {
+    use crate::listen::quiet_noise;
+
+    let config = Config {
+        decimation: 16,
+        sample_rate: 2_880_000,
+        deviation: 60_000,
+        shift: 0.,
+        filter: None,
+        energy: Some(EnergySquelch::default()),
+    };
+
+    // a carrier which never goes away, which holds the energy squelch open
+    let mut samples = quiet_noise(500_000, 3);
+    samples.extend(std::iter::repeat_n(Complex32::new(0.5, 0.), 8_000_000));
+
+    let mut squelch = Squelch::new(&config).expect("valid config");
+    let max_run = squelch.max_run;
+    let mut signals = Vec::new();
+    for block in samples.chunks(64 * 1024) {
+        signals.extend(squelch.push(block));
+    }
+    assert!(signals.len() >= 2, "{}", signals.len());
+    assert!(signals.iter().all(|signal| signal.fm.len() <= max_run));
+    assert!(squelch.run.len() <= max_run);
+}
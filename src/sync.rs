@@ -0,0 +1,168 @@
+//! find the sync word in the demodulated signal itself, before deciding on any bits
+//!
+//! The end of the preamble and "jack" are known, so we can correlate against what they'd look
+//! like, at a few bit periods, and either polarity. Every sample of the sync word contributes,
+//! rather than it having to survive bit slicing intact, so this works in much more noise.
+
+use crate::decode::KNOWN_HEADER_BYTES;
+use crate::encode::PREAMBLE;
+use itertools::Itertools;
+
+/// how much of the preamble is in the template; the squelch can trim the start of it
+const PREAMBLE_BYTES: usize = 2;
+/// how far from nominal the bit period is searched for, as a fraction of it
+const MAX_PERIOD_ERROR: f32 = 0.02;
+const PERIOD_STEP: f32 = 0.002;
+/// the weakest correlation (-1 to 1) we'll call a sync word
+const MIN_SCORE: f32 = 0.3;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SyncMatch {
+    /// the sample the template starts at; the length byte starts `template_bits` periods later
+    pub at: usize,
+    pub samples_per_bit: f32,
+    /// a one is a negative frequency
+    pub inverted: bool,
+    /// the correlation, 0 to 1
+    pub score: f32,
+}
+
+/// the end of the preamble, then "jack", ones first
+fn template() -> Vec<bool> {
+    PREAMBLE[PREAMBLE.len() - PREAMBLE_BYTES..]
+        .iter()
+        .chain(KNOWN_HEADER_BYTES)
+        .flat_map(|byte| (0..8).rev().map(move |i| byte >> i & 1 == 1))
+        .collect()
+}
+
+/// the best match for the template in a normalised (roughly -1 to 1) demodulated stream
+pub fn find_sync(input: &[f32], samples_per_bit: f32) -> Option<SyncMatch> {
+    let bits = template();
+    let mut sums = Vec::with_capacity(input.len() + 1);
+    sums.push(0f64);
+    for &v in input {
+        sums.push(sums.last().expect("non-empty") + f64::from(v.signum()));
+    }
+
+    let steps = (MAX_PERIOD_ERROR / PERIOD_STEP).round() as i32;
+    let mut best: Option<SyncMatch> = None;
+    for step in -steps..=steps {
+        let period = samples_per_bit * (1. + step as f32 * PERIOD_STEP);
+        let edges = (0..=bits.len())
+            .map(|i| (i as f32 * period).round() as usize)
+            .collect_vec();
+        let span = *edges.last().expect("non-empty");
+
+        for at in 0..input.len().saturating_sub(span) {
+            let corr = bits
+                .iter()
+                .zip(edges.iter().tuple_windows())
+                .map(|(&bit, (&start, &end))| {
+                    let sum = sums[at + end] - sums[at + start];
+                    if bit { sum } else { -sum }
+                })
+                .sum::<f64>();
+            let score = (corr / span as f64) as f32;
+            if best.is_none_or(|b| score.abs() > b.score) {
+                best = Some(SyncMatch {
+                    at,
+                    samples_per_bit: period,
+                    inverted: score < 0.,
+                    score: score.abs(),
+                });
+            }
+        }
+    }
+
+    best.filter(|b| b.score >= MIN_SCORE)
+}
+
+/// the bits after the sync word, from the length byte, by the middle half of each bit, and
+/// how sure we are of them, from 0 to 1
+pub fn slice(input: &[f32], sync: &SyncMatch) -> (Vec<bool>, Vec<f32>) {
+    let period = sync.samples_per_bit;
+    let data = sync.at as f32 + template().len() as f32 * period;
+
+    let mut bits = Vec::with_capacity((input.len() as f32 / period) as usize);
+    let mut confidence = Vec::with_capacity(bits.capacity());
+    for i in 0.. {
+        let start = (data + (i as f32 + 0.25) * period).round() as usize;
+        let end = (data + (i as f32 + 0.75) * period).round() as usize;
+        let Some(middle) = input.get(start..end.max(start + 1)) else {
+            break;
+        };
+        let mean = middle.iter().map(|v| v.signum()).sum::<f32>() / middle.len() as f32;
+        bits.push((mean > 0.) != sync.inverted);
+        confidence.push(mean.abs().min(1.));
+    }
+    (bits, confidence)
+}
+
+#[test]
+fn test_find_sync() {
+    use crate::modulate::Rng;
+
+    // some noise, the sync word (inverted), then the length byte
+    let mut rng = Rng::new(3);
+    let mut on_air = template();
+    on_air.extend([false, false, false, true, true, false, true, false]);
+    let period = 18.3;
+    let mut input = (0..100).map(|_| rng.gaussian() * 0.3).collect_vec();
+    for i in 0..(on_air.len() as f32 * period) as usize {
+        let bit = on_air[(i as f32 / period) as usize];
+        input.push(if bit { -1. } else { 1. } + rng.gaussian() * 0.8);
+    }
+
+    let sync = find_sync(&input, 18.).expect("found");
+    assert!(sync.inverted);
+    assert!(sync.at.abs_diff(100) <= 2, "{sync:?}");
+    assert!((sync.samples_per_bit - period).abs() < 0.1, "{sync:?}");
+
+    let (bits, _) = slice(&input, &sync);
+    assert_eq!(bits[..8], on_air[on_air.len() - 8..]);
+
+    // nothing in noise
+    let noise = (0..2000).map(|_| rng.gaussian() * 0.5).collect_vec();
+    assert_eq!(find_sync(&noise, 18.), None);
+}
+
+/// a synthetic transmission in more noise than the clock sweep can cope with
+#[test]
+fn test_correlate_in_noise() {
+    use crate::decode::{self, Clock, squelch_decode, synthesise};
+    use crate::encode::encode;
+    use crate::frame::Frame;
+    use crate::modulate;
+
+    let frame = Frame::parse(b"RF9\x01\x00\x10KWK1CGQ11AHZL0CGQ11A\x03\x00\x04\x00\x01\x02\x03")
+        .expect("valid frame");
+    let correlate = decode::Config {
+        clock: Clock::Correlate,
+        ..decode::Config::default()
+    };
+    let decodes = |config: &modulate::Config, decoder: &decode::Config| {
+        squelch_decode(&synthesise(&encode(&frame), config), config, decoder)
+            .iter()
+            .flat_map(|v| v.matches_crc.keys())
+            .any(|v| Frame::parse(v).as_ref() == Ok(&frame))
+    };
+
+    let configs = (0..8)
+        .map(|seed| modulate::Config {
+            noise: 0.25,
+            bt: Some(0.5),
+            seed,
+            ..modulate::Config::default()
+        })
+        .collect_vec();
+    let decoded = configs.iter().filter(|c| decodes(c, &correlate)).count();
+    assert!(decoded >= 6, "{decoded}");
+
+    // the sweep decodes none of these; it's slow to fail, so only try a few
+    let swept = configs[..4]
+        .iter()
+        .filter(|c| decodes(c, &decode::Config::default()))
+        .count();
+    assert_eq!(swept, 0);
+}